@@ -0,0 +1,158 @@
+use crate::geom::Point;
+use crate::renderer::TextureAtlas;
+use crate::texture::RgbaImage;
+
+/// How an atlas texture fills the canvas.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFit {
+    /// Repeated at its own size from the top left corner.
+    Tile,
+    /// Scaled keeping its aspect ratio until it covers the whole canvas, centred and cropped.
+    Cover,
+}
+
+/// What the canvas shows behind all sprites, see `RenderBackend::set_background`. It is fixed
+/// to the screen, the camera does not move it. Colors are straight RGBA from 0 to 1.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Background {
+    /// Nothing, the page below the canvas shows through.
+    Transparent,
+    Color([f32; 4]),
+    /// From `top` at the top edge to `bottom` at the bottom edge.
+    VerticalGradient { top: [f32; 4], bottom: [f32; 4] },
+    /// From `inner` at the centre to `outer` at the corners.
+    RadialGradient { inner: [f32; 4], outer: [f32; 4] },
+    /// Texture of the atlas, e.g. a photo. Nothing is drawn while the atlas lacks it.
+    Image { texture: usize, fit: ImageFit },
+}
+
+impl Default for Background {
+    /// Opaque black.
+    fn default() -> Background {
+        Background::Color([0.0, 0.0, 0.0, 1.0])
+    }
+}
+
+impl Background {
+    /// Whether every pixel of the cleared frame ends up with alpha 1. Images may have
+    /// transparent pixels, or be missing from the atlas, so they do not count.
+    pub fn is_opaque(&self) -> bool {
+        match *self {
+            Background::Color(color) => color[3] >= 1.0,
+            Background::VerticalGradient { top: a, bottom: b } | Background::RadialGradient { inner: a, outer: b } =>
+                a[3] >= 1.0 && b[3] >= 1.0,
+            Background::Transparent | Background::Image { .. } => false,
+        }
+    }
+
+    /// Premultiplied color the frame is cleared to before the backdrop is drawn.
+    pub fn clear_color(&self) -> [f32; 4] {
+        match *self {
+            Background::Color(color) => premultiply(color),
+            _ => [0.0; 4]
+        }
+    }
+
+    /// Full-screen pass drawing gradients and images, `None` when clearing is enough.
+    pub fn backdrop(&self, atlas: &TextureAtlas) -> Option<Backdrop> {
+        match *self {
+            Background::Transparent | Background::Color(_) => None,
+            Background::VerticalGradient { top, bottom } =>
+                Some(Backdrop { program: 0, params: colors(top, bottom), page: None }),
+            Background::RadialGradient { inner, outer } =>
+                Some(Backdrop { program: 1, params: colors(inner, outer), page: None }),
+            Background::Image { texture, fit } => {
+                if texture >= atlas.len() {
+                    return None;
+                }
+                let (page, item) = atlas.region(texture);
+                let (width, height) = (page.width as f32, page.height as f32);
+                let params = [
+                    item.x as f32 / width, item.y as f32 / height,
+                    (item.x + item.width) as f32 / width, (item.y + item.height) as f32 / height,
+                    item.width as f32, item.height as f32, 0.0, 0.0,
+                ];
+                let program = match fit {
+                    ImageFit::Tile => 2,
+                    ImageFit::Cover => 3,
+                };
+                Some(Backdrop { program, params, page: Some(item.page) })
+            }
+        }
+    }
+}
+
+/// Background pass, drawn like a post effect pass over the cleared frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Backdrop {
+    /// Index of the program in `background_shaders`.
+    pub program: usize,
+    /// Values of `u_params`: two straight colors for gradients, the texture rectangle on its
+    /// page and its size in pixels for images.
+    pub params: [f32; 8],
+    /// Atlas page sampled by images.
+    pub page: Option<usize>,
+}
+
+impl Backdrop {
+    /// Software version of the program: premultiplied color at pixel centre `p`, measured from
+    /// the top left corner of a canvas of `size`. `page` is the premultiplied atlas page.
+    pub fn shade(&self, p: Point, size: Point, page: Option<&RgbaImage>) -> [f32; 4] {
+        let [a0, a1, a2, a3, b0, b1, b2, b3] = self.params;
+        let (a, b) = ([a0, a1, a2, a3], [b0, b1, b2, b3]);
+        let local = match self.program {
+            0 | 1 => {
+                let t = if self.program == 0 { p.y / size.y } else { (p - size * 0.5).length() / (size * 0.5).length() };
+                let t = t.clamp(0.0, 1.0);
+                return premultiply([0, 1, 2, 3].map(|c| a[c] + (b[c] - a[c]) * t));
+            }
+            2 => Point { x: fract(p.x / b0), y: fract(p.y / b1) },
+            _ => {
+                let scale = (size.x / b0).max(size.y / b1);
+                let image = Point { x: b0 * scale, y: b1 * scale };
+                let offset = p - (size - image) * 0.5;
+                Point { x: offset.x / image.x, y: offset.y / image.y }
+            }
+        };
+        match page {
+            Some(page) => page.sample(a0 + (a2 - a0) * local.x, a1 + (a3 - a1) * local.y),
+            None => [0.0; 4]
+        }
+    }
+}
+
+fn colors(a: [f32; 4], b: [f32; 4]) -> [f32; 8] {
+    [a[0], a[1], a[2], a[3], b[0], b[1], b[2], b[3]]
+}
+
+fn premultiply(color: [f32; 4]) -> [f32; 4] {
+    [color[0] * color[3], color[1] * color[3], color[2] * color[3], color[3]]
+}
+
+/// Same as GLSL `fract`.
+fn fract(x: f32) -> f32 {
+    x - x.floor()
+}
+
+static BACKGROUND_HEADER: &str = "precision mediump float;
+uniform sampler2D u_image; uniform vec2 u_size; uniform vec4 u_params[2];
+// pixel centre from the top left corner of the canvas
+vec2 position() { return vec2(gl_FragCoord.x, u_size.y - gl_FragCoord.y); }
+vec4 gradient(float t) { vec4 c = mix(u_params[0], u_params[1], clamp(t, 0.0, 1.0)); return vec4(c.rgb * c.a, c.a); }
+vec4 sample_image(vec2 local) { return texture2D(u_image, mix(u_params[0].xy, u_params[0].zw, local)); }
+";
+
+/// Fragment stages of the backgrounds, by `Backdrop::program`. The vertex stage is
+/// `PASS_VERTEX_SHADER`.
+pub fn background_shaders() -> [String; 4] {
+    [
+        format!("{}void main() {{ gl_FragColor = gradient(position().y / u_size.y); }}", BACKGROUND_HEADER),
+        format!("{}void main() {{ gl_FragColor = gradient(length(position() - u_size * 0.5) / length(u_size * 0.5)); }}",
+                BACKGROUND_HEADER),
+        format!("{}void main() {{ gl_FragColor = sample_image(fract(position() / u_params[1].xy)); }}", BACKGROUND_HEADER),
+        format!("{}void main() {{
+  vec2 image_size = u_params[1].xy * max(u_size.x / u_params[1].x, u_size.y / u_params[1].y);
+  gl_FragColor = sample_image((position() - (u_size - image_size) * 0.5) / image_size);
+}}", BACKGROUND_HEADER),
+    ]
+}
@@ -0,0 +1,149 @@
+use std::marker::PhantomData;
+use std::mem;
+use std::ops::Range;
+
+/// Smallest buffer allocation in bytes, so that the first frames do not reallocate repeatedly.
+const MIN_CAPACITY: usize = 1024;
+
+/// What has to be done to bring a GPU buffer up to date with new contents.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BufferUpdate {
+    /// Contents did not change.
+    None,
+    /// Storage is too small: allocate `capacity` bytes and upload all contents.
+    Reallocate { capacity: usize },
+    /// Upload only the bytes in `range`, the rest of the buffer is up to date.
+    Update { range: Range<usize> },
+}
+
+/// Allocated storage of a GPU buffer filled from a `Staging` buffer. Storage grows
+/// geometrically and is never shrunk, what changed is tracked by the staging buffer.
+pub struct BufferState {
+    capacity: usize,
+}
+
+impl BufferState {
+    pub fn create() -> BufferState {
+        BufferState { capacity: 0 }
+    }
+
+    /// Allocated size of the GPU buffer in bytes.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Returns how to upload the staged contents, which count as uploaded afterwards.
+    pub fn update<T: Element>(&mut self, staging: &mut Staging<T>) -> BufferUpdate {
+        let len = staging.as_bytes().len();
+        let dirty = staging.take_dirty();
+        if len > self.capacity {
+            self.capacity = len.max(self.capacity * 2).max(MIN_CAPACITY);
+            // the new storage holds nothing past the staged bytes
+            staging.discard_unused();
+            BufferUpdate::Reallocate { capacity: self.capacity }
+        } else {
+            match dirty {
+                Some(range) => BufferUpdate::Update { range },
+                None => BufferUpdate::None
+            }
+        }
+    }
+
+    /// Forgets the GPU storage, e.g. after the context was lost.
+    pub fn reset(&mut self) {
+        self.capacity = 0;
+    }
+}
+
+/// Numbers stored in a `Staging` buffer.
+pub trait Element: Copy {
+    /// Writes the little endian bytes, at most 8, into `bytes` of the element's size.
+    fn write(self, bytes: &mut [u8]);
+}
+
+impl Element for f32 {
+    fn write(self, bytes: &mut [u8]) {
+        bytes.copy_from_slice(&self.to_le_bytes());
+    }
+}
+
+impl Element for u16 {
+    fn write(self, bytes: &mut [u8]) {
+        bytes.copy_from_slice(&self.to_le_bytes());
+    }
+}
+
+/// CPU side vertex or index data, reused from frame to frame. Elements are kept as the
+/// little endian bytes WebGL reads, so uploading is copying from a plain byte slice and
+/// never needs a view into wasm memory, which any allocation could invalidate.
+///
+/// Refilling after `clear` overwrites the previous contents in place and records the range
+/// of bytes that differ, so unchanged frames need no upload.
+pub struct Staging<T: Element> {
+    bytes: Vec<u8>,
+    len: usize,
+    dirty: Option<Range<usize>>,
+    element: PhantomData<T>,
+}
+
+impl<T: Element> Staging<T> {
+    pub fn create() -> Staging<T> {
+        Staging { bytes: Vec::new(), len: 0, dirty: None, element: PhantomData }
+    }
+
+    /// Empties the buffer keeping its storage and previous contents to compare against.
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    pub fn push(&mut self, elements: &[T]) {
+        let size = mem::size_of::<T>();
+        let mut written = [0_u8; 8];
+        for element in elements.iter() {
+            let (start, end) = (self.len, self.len + size);
+            element.write(&mut written[..size]);
+            if end > self.bytes.len() {
+                self.bytes.extend_from_slice(&written[..size]);
+                self.mark_dirty(start..end);
+            } else if self.bytes[start..end] != written[..size] {
+                self.bytes[start..end].copy_from_slice(&written[..size]);
+                self.mark_dirty(start..end);
+            }
+            self.len = end;
+        }
+    }
+
+    fn mark_dirty(&mut self, range: Range<usize>) {
+        self.dirty = Some(match self.dirty.take() {
+            Some(dirty) => dirty.start.min(range.start)..dirty.end.max(range.end),
+            None => range
+        });
+    }
+
+    /// Bytes changed since the last call, limited to the staged elements.
+    pub fn take_dirty(&mut self) -> Option<Range<usize>> {
+        let len = self.len;
+        self.dirty.take()
+            .filter(|dirty| dirty.start < len)
+            .map(|dirty| dirty.start..dirty.end.min(len))
+    }
+
+    /// Drops stale bytes past the staged elements, so that staging them again counts as a
+    /// change, e.g. after the GPU buffer was reallocated without them.
+    pub fn discard_unused(&mut self) {
+        self.bytes.truncate(self.len);
+    }
+
+    /// Number of elements.
+    pub fn len(&self) -> usize {
+        self.len / mem::size_of::<T>()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.len]
+    }
+}
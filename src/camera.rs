@@ -0,0 +1,86 @@
+use crate::geom::{Mat3, Point};
+
+/// Range `zoom_at` keeps the zoom in, the view never collapses or blows up.
+pub const MIN_ZOOM: f32 = 0.1;
+pub const MAX_ZOOM: f32 = 20.0;
+
+/// Maps a pointer position in CSS pixels, e.g. `MouseEvent::client_x`, to screen pixels of a
+/// canvas with its top left corner at `canvas_origin` in CSS pixels and `pixel_ratio` device
+/// pixels per CSS pixel, the space the camera viewport is in.
+pub fn client_to_screen(client: Point, canvas_origin: Point, pixel_ratio: f32) -> Point {
+    (client - canvas_origin) * pixel_ratio
+}
+
+/// View into the world: the world point shown at the viewport centre, zoom and rotation.
+/// World coordinates are pixels, so a camera fresh from `create` shows the world as is.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Camera {
+    /// World point at the centre of the viewport.
+    pub position: Point,
+    /// Screen pixels per world unit, above 0.
+    pub zoom: f32,
+    /// Angle the world is turned by on screen, clockwise in radians.
+    pub rotation: f32,
+    viewport: Point,
+}
+
+impl Camera {
+    pub fn create(viewport_width: u32, viewport_height: u32) -> Camera {
+        let viewport = Point { x: viewport_width as f32, y: viewport_height as f32 };
+        Camera { position: viewport * 0.5, zoom: 1.0, rotation: 0.0, viewport }
+    }
+
+    /// Follows a new viewport size keeping the world point at the top left corner in place.
+    pub fn resize(&mut self, viewport_width: u32, viewport_height: u32) {
+        let corner = self.screen_to_world(Point { x: 0.0, y: 0.0 });
+        self.viewport = Point { x: viewport_width as f32, y: viewport_height as f32 };
+        self.position += corner - self.screen_to_world(Point { x: 0.0, y: 0.0 });
+    }
+
+    /// Transform from world space to screen pixels.
+    pub fn view_matrix(&self) -> Mat3 {
+        Mat3::translation(self.viewport * 0.5)
+            * Mat3::scale(Point { x: self.zoom, y: self.zoom })
+            * Mat3::rotation(self.rotation)
+            * Mat3::translation(-self.position)
+    }
+
+    /// Transform from screen pixels to world space, the inverse of `view_matrix`.
+    pub fn inverse_view_matrix(&self) -> Mat3 {
+        Mat3::translation(self.position)
+            * Mat3::rotation(-self.rotation)
+            * Mat3::scale(Point { x: 1.0 / self.zoom, y: 1.0 / self.zoom })
+            * Mat3::translation(-self.viewport * 0.5)
+    }
+
+    pub fn world_to_screen(&self, p: Point) -> Point {
+        self.view_matrix().transform_point(p)
+    }
+
+    pub fn screen_to_world(&self, p: Point) -> Point {
+        self.inverse_view_matrix().transform_point(p)
+    }
+
+    /// Moves the view by `delta` screen pixels, the world follows as if dragged.
+    pub fn pan(&mut self, delta: Point) {
+        self.position -= self.inverse_view_matrix().transform_vector(delta);
+    }
+
+    /// Multiplies the zoom by `factor` keeping the world point under `screen_point` in place.
+    /// The zoom stays within `MIN_ZOOM` and `MAX_ZOOM`, factors that are not positive are ignored.
+    pub fn zoom_at(&mut self, screen_point: Point, factor: f32) {
+        if !(factor > 0.0 && factor.is_finite()) {
+            return;
+        }
+        let anchor = self.screen_to_world(screen_point);
+        self.zoom = (self.zoom * factor).clamp(MIN_ZOOM, MAX_ZOOM);
+        self.position += anchor - self.screen_to_world(screen_point);
+    }
+
+    /// Turns the view by `angle` around the world point under `screen_point`.
+    pub fn rotate_at(&mut self, screen_point: Point, angle: f32) {
+        let anchor = self.screen_to_world(screen_point);
+        self.rotation += angle;
+        self.position += anchor - self.screen_to_world(screen_point);
+    }
+}
@@ -0,0 +1,355 @@
+use wasm_bindgen::{Clamped, JsCast, JsValue};
+use web_sys::{CanvasRenderingContext2d, HtmlCanvasElement, ImageData};
+
+use crate::background::Background;
+use crate::geom::Point;
+use crate::logger::{log_debug, log_error, log_info};
+use crate::packer::AtlasConfig;
+use crate::layer::{Layer, LayerSet};
+use crate::material::{Fragment, MaterialId, Materials, Shader, ShaderId, UniformValue};
+use crate::postprocess::{apply_effects, PostEffect, PostEffects};
+use crate::renderer::{batch_visible_sprites, compose_atlas, BatchOrder, BlendMode, FrameStats, PageContent, Projection,
+                      RenderBackend, ResourceCounts, Sprite, TextureAtlas, Vertex};
+use crate::resource_manager::Registry;
+use crate::texture::{RgbaImage, TextureSource};
+
+/// Atlas page size limit, matches what most WebGL implementations support.
+const MAX_TEXTURE_SIZE: u32 = 4096;
+
+/// Software rasterizer producing the same textured, rotated, alpha-blended quads as
+/// the WebGL `Renderer`. Renders into an RGBA frame, which is either inspected directly
+/// (headless, e.g. in tests) or presented on a 2d canvas when WebGL is not available.
+pub struct CpuRenderer {
+    frame: RgbaImage,
+    textures: Registry<RgbaImage>,
+    context: Option<CanvasRenderingContext2d>,
+    atlas_config: AtlasConfig,
+    batch_order: BatchOrder,
+    visible_layers: LayerSet,
+    materials: Materials,
+    effects: PostEffects,
+    background: Background,
+    stats: FrameStats,
+}
+
+impl CpuRenderer {
+    /// Headless renderer, the result is read with `frame`.
+    pub fn create(width: u32, height: u32) -> CpuRenderer {
+        CpuRenderer {
+            frame: RgbaImage::create(width, height),
+            textures: Registry::create(),
+            context: None,
+            atlas_config: AtlasConfig::create(MAX_TEXTURE_SIZE),
+            batch_order: BatchOrder::Preserve,
+            visible_layers: LayerSet::all(),
+            materials: Materials::create(),
+            effects: PostEffects::default(),
+            background: Background::default(),
+            stats: FrameStats::default(),
+        }
+    }
+
+    /// Renderer presenting frames on the canvas with a 2d context.
+    pub fn init(canvas: &HtmlCanvasElement) -> Result<CpuRenderer, JsValue> {
+        let context = canvas.get_context("2d")?.ok_or("2d canvas is not supported")?;
+        let context = context.dyn_into::<CanvasRenderingContext2d>()?;
+        let mut renderer = CpuRenderer::create(canvas.width(), canvas.height());
+        renderer.context = Some(context);
+        log_info("Software renderer initialized");
+        Ok(renderer)
+    }
+
+    pub fn frame(&self) -> &RgbaImage {
+        &self.frame
+    }
+
+    /// Turns page content into a texture: reads back the canvas, if any, and copies pixel sources over it.
+    fn read_page(page: &PageContent) -> Result<RgbaImage, JsValue> {
+        let mut texture = match &page.canvas {
+            Some(canvas) => {
+                let context = canvas.get_context("2d")?.ok_or("2d canvas is not supported")?;
+                let context = context.dyn_into::<CanvasRenderingContext2d>()?;
+                let data = context.get_image_data(0_f64, 0_f64, page.width as f64, page.height as f64)?;
+                RgbaImage { width: data.width(), height: data.height(), pixels: data.data().0 }
+            }
+            None => RgbaImage::create(page.width, page.height)
+        };
+        for (x, y, image) in page.pixels.iter() {
+            texture.blit(image, *x, *y);
+        }
+        // stored premultiplied like WebGL uploads, see `BlendMode`
+        Ok(texture.premultiplied())
+    }
+
+    /// Runs the effects of `layer` on what was drawn on it and composes the result with the frame.
+    fn finish_layer(&mut self, layer: Layer, image: RgbaImage) {
+        let effects = self.effects.layer(layer);
+        self.stats.post_passes += effects.iter().map(|effect| effect.passes().len()).sum::<usize>();
+        let image = apply_effects(effects, image);
+        for y in 0..image.height {
+            for x in 0..image.width {
+                let color = image.pixel(x, y).map(|value| value as f32 / 255.0);
+                blend(&mut self.frame, x, y, color, BlendMode::Premultiplied);
+            }
+        }
+    }
+
+    /// Clears the frame and draws gradients and images of the background.
+    fn draw_background(&mut self, atlas: &TextureAtlas) {
+        self.frame.fill(self.background.clear_color().map(|value| (value * 255.0).round() as u8));
+        let backdrop = match self.background.backdrop(atlas) {
+            Some(backdrop) => backdrop,
+            None => return
+        };
+        let textures = &self.textures;
+        let page = backdrop.page.and_then(|page| textures.get(atlas.pages()[page].texture));
+        let size = Point { x: self.frame.width as f32, y: self.frame.height as f32 };
+        for y in 0..self.frame.height {
+            for x in 0..self.frame.width {
+                let color = backdrop.shade(Point { x: x as f32 + 0.5, y: y as f32 + 0.5 }, size, page);
+                blend(&mut self.frame, x, y, color, BlendMode::Premultiplied);
+            }
+        }
+    }
+
+    fn present(&self) -> Result<(), JsValue> {
+        if let Some(context) = &self.context {
+            // the canvas takes straight alpha, over an opaque background both are the same
+            let unpremultiplied;
+            let frame = if self.background.is_opaque() {
+                &self.frame
+            } else {
+                unpremultiplied = self.frame.unpremultiplied();
+                &unpremultiplied
+            };
+            let data = ImageData::new_with_u8_clamped_array_and_sh(
+                Clamped(frame.pixels.as_slice()), frame.width, frame.height)?;
+            context.put_image_data(&data, 0_f64, 0_f64)?;
+        }
+        Ok(())
+    }
+
+}
+
+fn draw_triangle(frame: &mut RgbaImage, texture: &RgbaImage, shader: &Shader, uniforms: &[UniformValue],
+                 mode: BlendMode, v: [Vertex; 3]) {
+    let area = edge(v[0].position, v[1].position, v[2].position);
+    if area == 0.0 {
+        return;
+    }
+    // keep a single winding so that the top-left fill rule below holds
+    let v = if area < 0.0 { [v[0], v[2], v[1]] } else { v };
+    let area = area.abs();
+    let (a, b, c) = (v[0].position, v[1].position, v[2].position);
+    let min_x = a.x.min(b.x).min(c.x).floor().max(0.0) as u32;
+    let min_y = a.y.min(b.y).min(c.y).floor().max(0.0) as u32;
+    let max_x = (a.x.max(b.x).max(c.x).ceil().max(0.0) as u32).min(frame.width);
+    let max_y = (a.y.max(b.y).max(c.y).ceil().max(0.0) as u32).min(frame.height);
+    let bias = [is_top_left(b, c), is_top_left(c, a), is_top_left(a, b)];
+    for y in min_y..max_y {
+        for x in min_x..max_x {
+            let p = Point { x: x as f32 + 0.5, y: y as f32 + 0.5 };
+            let w = [edge(b, c, p), edge(c, a, p), edge(a, b, p)];
+            let inside = w.iter().zip(bias.iter()).all(|(w, top_left)| *w > 0.0 || (*w == 0.0 && *top_left));
+            if !inside {
+                continue;
+            }
+            let l = [w[0] / area, w[1] / area, w[2] / area];
+            let u = l[0] * v[0].tex_coord.x + l[1] * v[1].tex_coord.x + l[2] * v[2].tex_coord.x;
+            let t = l[0] * v[0].tex_coord.y + l[1] * v[1].tex_coord.y + l[2] * v[2].tex_coord.y;
+            let alpha = l[0] * v[0].alpha + l[1] * v[1].alpha + l[2] * v[2].alpha;
+            let fragment = Fragment::create(texture, Point { x: u, y: t }, uniforms, v[0].attributes, mode.premultiplied());
+            let mut color = (shader.cpu)(&fragment);
+            // the tint is constant over a quad, the shaders clamp the tinted color before alpha applies too
+            for (c, value) in color.iter_mut().enumerate() {
+                *value = (*value * v[0].tint[c] + v[0].tint_add[c]).clamp(0.0, 1.0);
+            }
+            if !mode.premultiplied() {
+                for c in 0..3 {
+                    color[c] *= color[3];
+                }
+            }
+            blend(frame, x, y, color.map(|value| value * alpha), mode);
+        }
+    }
+}
+
+/// Same as the `blend_func` factors of `mode` for all four channels, `src` is premultiplied.
+fn blend(frame: &mut RgbaImage, x: u32, y: u32, src: [f32; 4], mode: BlendMode) {
+    let dst = frame.pixel(x, y);
+    let a = src[3];
+    let mut out = [0_u8; 4];
+    for c in 0..4 {
+        let d = dst[c] as f32 / 255.0;
+        let value = match mode {
+            BlendMode::Normal | BlendMode::Premultiplied => src[c] + d * (1.0 - a),
+            BlendMode::Additive => src[c] + d,
+            BlendMode::Multiply => src[c] * d + d * (1.0 - a),
+            BlendMode::Screen => src[c] + d * (1.0 - src[c]),
+        };
+        out[c] = (value * 255.0).round().clamp(0.0, 255.0) as u8;
+    }
+    frame.set_pixel(x, y, out);
+}
+
+impl RenderBackend for CpuRenderer {
+    fn create_atlas(&mut self, sources: &[TextureSource]) -> Result<TextureAtlas, JsValue> {
+        let (mut atlas, pages) = compose_atlas(sources, &self.atlas_config)?;
+        for (index, page) in pages.iter().enumerate() {
+            let id = self.textures.insert(CpuRenderer::read_page(page)?);
+            atlas.set_page_texture(index, id);
+        }
+        Ok(atlas)
+    }
+
+    fn set_atlas_config(&mut self, config: AtlasConfig) {
+        self.atlas_config = config;
+    }
+
+    fn set_batch_order(&mut self, order: BatchOrder) {
+        self.batch_order = order;
+    }
+
+    fn set_layer_visible(&mut self, layer: Layer, visible: bool) {
+        self.visible_layers.set(layer, visible);
+    }
+
+    fn add_shader(&mut self, shader: Shader) -> Result<ShaderId, JsValue> {
+        // shaders run as their `cpu` functions, there is nothing to compile
+        Ok(self.materials.add_shader(shader)?)
+    }
+
+    fn add_material(&mut self, shader: ShaderId) -> Result<MaterialId, JsValue> {
+        Ok(self.materials.add_material(shader)?)
+    }
+
+    fn set_uniform(&mut self, material: MaterialId, name: &str, value: UniformValue) -> Result<(), JsValue> {
+        Ok(self.materials.set_uniform(material, name, value)?)
+    }
+
+    fn materials(&self) -> &Materials {
+        &self.materials
+    }
+
+    fn set_post_effects(&mut self, effects: Vec<PostEffect>) {
+        self.effects.frame = effects;
+    }
+
+    fn set_layer_effects(&mut self, layer: Layer, effects: Vec<PostEffect>) {
+        self.effects.set_layer(layer, effects);
+    }
+
+    fn set_background(&mut self, background: Background) {
+        self.background = background;
+    }
+
+    fn delete_atlas(&mut self, atlas: TextureAtlas) {
+        for page in atlas.pages().iter() {
+            self.textures.remove(page.texture);
+        }
+    }
+
+    fn resources(&self) -> ResourceCounts {
+        ResourceCounts { textures: self.textures.len(), buffers: 0, programs: 0 }
+    }
+
+    fn frame_stats(&self) -> FrameStats {
+        self.stats
+    }
+
+    fn resize(&mut self, width: u32, height: u32) {
+        if let Some(canvas) = self.context.as_ref().and_then(|context| context.canvas()) {
+            canvas.set_width(width);
+            canvas.set_height(height);
+        }
+        self.frame = RgbaImage::create(width, height);
+    }
+
+    fn restore(&mut self) -> Result<(), JsValue> {
+        // nothing is lost in memory, only keep the contract that atlases are created again
+        self.textures.drain();
+        Ok(())
+    }
+
+    fn render(&mut self, projection: &Projection, sprites: &[Sprite], atlas: &TextureAtlas) {
+        if self.frame.width != projection.canvas_width || self.frame.height != projection.canvas_height {
+            self.frame = RgbaImage::create(projection.canvas_width, projection.canvas_height);
+        }
+        self.draw_background(atlas);
+        let width = self.frame.width as f32;
+        let height = self.frame.height as f32;
+        let visible = projection.visible_rect();
+        let (draw_order, batches) = batch_visible_sprites(sprites, atlas, self.batch_order, &visible, self.visible_layers);
+        self.stats = FrameStats {
+            drawn_sprites: draw_order.len(),
+            culled_sprites: sprites.len() - draw_order.len(),
+            ..FrameStats::default()
+        };
+        // layers with effects are drawn on a transparent image of their own first
+        let mut layer: Option<(Layer, Option<RgbaImage>)> = None;
+        for batch in batches.iter() {
+            if layer.as_ref().map(|(current, _)| *current) != Some(batch.layer) {
+                if let Some((previous, Some(image))) = layer.take() {
+                    self.finish_layer(previous, image);
+                }
+                let image = if self.effects.layer(batch.layer).is_empty() {
+                    None
+                } else {
+                    Some(RgbaImage::create(self.frame.width, self.frame.height))
+                };
+                layer = Some((batch.layer, image));
+            }
+            let target = match &mut layer {
+                Some((_, Some(image))) => image,
+                _ => &mut self.frame
+            };
+            let texture = match self.textures.get(atlas.pages()[batch.page].texture) {
+                Some(texture) => texture,
+                None => continue
+            };
+            let material = match self.materials.material(batch.material) {
+                Some(material) => material,
+                None => continue
+            };
+            let shader = self.materials.shader(material.shader).unwrap();
+            for &i in draw_order[batch.start..batch.start + batch.count].iter() {
+                let mut quad = sprites[i].quad(atlas);
+                for v in quad.iter_mut() {
+                    let clip = projection.to_clip(v.position);
+                    v.position = Point { x: (clip.x + 1.0) * 0.5 * width, y: (1.0 - clip.y) * 0.5 * height };
+                }
+                draw_triangle(target, texture, shader, &material.values, batch.blend, [quad[0], quad[1], quad[2]]);
+                draw_triangle(target, texture, shader, &material.values, batch.blend, [quad[0], quad[2], quad[3]]);
+            }
+            self.stats.draw_calls += 1;
+        }
+        if let Some((previous, Some(image))) = layer {
+            self.finish_layer(previous, image);
+        }
+        if !self.effects.frame.is_empty() {
+            self.stats.post_passes += self.effects.frame.iter().map(|effect| effect.passes().len()).sum::<usize>();
+            let frame = std::mem::replace(&mut self.frame, RgbaImage::create(0, 0));
+            self.frame = apply_effects(&self.effects.frame, frame);
+        }
+        if let Err(e) = self.present() {
+            log_error(format!("Software renderer: failed to present frame, {:?}", &e).as_str());
+        }
+        log_debug("Software renderer: render completed");
+    }
+}
+
+/// Twice the signed area of triangle (a, b, p). Always evaluated from the same end of the edge,
+/// so that the two triangles sharing an edge get exactly opposite values and no pixel on it is lost.
+fn edge(a: Point, b: Point, p: Point) -> f32 {
+    if (a.x, a.y) > (b.x, b.y) {
+        return -edge(b, a, p);
+    }
+    (b.x - a.x) * (p.y - a.y) - (b.y - a.y) * (p.x - a.x)
+}
+
+/// Top or left edge of a triangle with positive `edge` area, y axis pointing down.
+fn is_top_left(a: Point, b: Point) -> bool {
+    let dx = b.x - a.x;
+    let dy = b.y - a.y;
+    (dy == 0.0 && dx > 0.0) || dy < 0.0
+}
@@ -1,7 +1,6 @@
 use core::ops;
-use js_sys::Math::{sin, cos};
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Point {
     pub x: f32,
     pub y: f32,
@@ -31,11 +30,226 @@ impl ops::Mul<f32> for Point {
     }
 }
 
+impl ops::Div<f32> for Point {
+    type Output = Point;
+
+    fn div(self, v: f32) -> Point {
+        Point { x: self.x / v, y: self.y / v }
+    }
+}
+
+impl ops::Neg for Point {
+    type Output = Point;
+
+    fn neg(self) -> Point {
+        Point { x: -self.x, y: -self.y }
+    }
+}
+
+impl ops::AddAssign<Point> for Point {
+    fn add_assign(&mut self, other: Point) {
+        *self = *self + other;
+    }
+}
+
+impl ops::SubAssign<Point> for Point {
+    fn sub_assign(&mut self, other: Point) {
+        *self = *self - other;
+    }
+}
+
+impl ops::MulAssign<f32> for Point {
+    fn mul_assign(&mut self, v: f32) {
+        *self = *self * v;
+    }
+}
+
+impl ops::DivAssign<f32> for Point {
+    fn div_assign(&mut self, v: f32) {
+        *self = *self / v;
+    }
+}
+
+/// Points double as vectors, e.g. offsets and directions.
+pub type Vec2 = Point;
+
 impl Point {
     pub fn rotate(&self, angle: f32) -> Point {
+        let (sin, cos) = angle.sin_cos();
+        self.rotate_sin_cos(sin, cos)
+    }
+
+    /// Rotation by an angle given as its sine and cosine, for rotating several points by the same angle.
+    pub fn rotate_sin_cos(&self, sin: f32, cos: f32) -> Point {
         Point {
-            x: self.x * cos(angle as f64) as f32 - self.y * sin(angle as f64) as f32,
-            y: self.x * sin(angle as f64) as f32 + self.y * cos(angle as f64) as f32
+            x: self.x * cos - self.y * sin,
+            y: self.x * sin + self.y * cos
+        }
+    }
+
+    pub fn dot(self, other: Point) -> f32 {
+        self.x * other.x + self.y * other.y
+    }
+
+    /// Z component of the 3D cross product: positive when `other` is clockwise from `self`
+    /// on screen, where y points down.
+    pub fn cross(self, other: Point) -> f32 {
+        self.x * other.y - self.y * other.x
+    }
+
+    pub fn length_squared(self) -> f32 {
+        self.dot(self)
+    }
+
+    pub fn length(self) -> f32 {
+        self.length_squared().sqrt()
+    }
+
+    /// Vector of length 1 in the same direction. The zero vector stays zero.
+    pub fn normalize(self) -> Point {
+        let length = self.length();
+        if length > 0.0 { self / length } else { self }
+    }
+
+    /// Linear interpolation: `self` at `t = 0`, `other` at `t = 1`.
+    pub fn lerp(self, other: Point, t: f32) -> Point {
+        self + (other - self) * t
+    }
+}
+
+/// Axis aligned rectangle, `x` and `y` being its top left corner.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rect {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Rect {
+    /// Smallest rectangle containing all `points`, `None` when there are none.
+    pub fn bounding(points: &[Point]) -> Option<Rect> {
+        let first = *points.first()?;
+        let (min, max) = points.iter().fold((first, first), |(min, max), p| (
+            Point { x: min.x.min(p.x), y: min.y.min(p.y) },
+            Point { x: max.x.max(p.x), y: max.y.max(p.y) }
+        ));
+        Some(Rect { x: min.x, y: min.y, width: max.x - min.x, height: max.y - min.y })
+    }
+
+    pub fn right(&self) -> f32 {
+        self.x + self.width
+    }
+
+    pub fn bottom(&self) -> f32 {
+        self.y + self.height
+    }
+
+    /// Includes the top and left edges but not the bottom and right ones.
+    pub fn contains(&self, p: Point) -> bool {
+        p.x >= self.x && p.x < self.right() && p.y >= self.y && p.y < self.bottom()
+    }
+
+    pub fn contains_rect(&self, other: &Rect) -> bool {
+        other.x >= self.x && other.right() <= self.right() && other.y >= self.y && other.bottom() <= self.bottom()
+    }
+
+    /// Whether the rectangles share any area, touching edges do not count.
+    pub fn intersects(&self, other: &Rect) -> bool {
+        self.intersection(other).is_some()
+    }
+
+    pub fn intersection(&self, other: &Rect) -> Option<Rect> {
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+        let right = self.right().min(other.right());
+        let bottom = self.bottom().min(other.bottom());
+        if right > x && bottom > y {
+            Some(Rect { x, y, width: right - x, height: bottom - y })
+        } else {
+            None
         }
     }
-}
\ No newline at end of file
+}
+
+/// 3x3 matrix of a 2D affine transform, stored column by column as WebGL expects it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Mat3 {
+    pub m: [f32; 9],
+}
+
+impl Mat3 {
+    pub fn identity() -> Mat3 {
+        Mat3 { m: [1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0] }
+    }
+
+    pub fn translation(t: Point) -> Mat3 {
+        Mat3 { m: [1.0, 0.0, 0.0, 0.0, 1.0, 0.0, t.x, t.y, 1.0] }
+    }
+
+    pub fn rotation(angle: f32) -> Mat3 {
+        let (sin, cos) = angle.sin_cos();
+        Mat3 { m: [cos, sin, 0.0, -sin, cos, 0.0, 0.0, 0.0, 1.0] }
+    }
+
+    pub fn scale(s: Point) -> Mat3 {
+        Mat3 { m: [s.x, 0.0, 0.0, 0.0, s.y, 0.0, 0.0, 0.0, 1.0] }
+    }
+
+    /// Shear by angles: `x` tilts vertical lines, `y` tilts horizontal ones.
+    pub fn skew(angles: Point) -> Mat3 {
+        Mat3 { m: [1.0, angles.y.tan(), 0.0, angles.x.tan(), 1.0, 0.0, 0.0, 0.0, 1.0] }
+    }
+
+    pub fn transform_point(&self, p: Point) -> Point {
+        let m = &self.m;
+        Point { x: m[0] * p.x + m[3] * p.y + m[6], y: m[1] * p.x + m[4] * p.y + m[7] }
+    }
+
+    /// Transforms a direction or offset: like `transform_point` but without the translation.
+    pub fn transform_vector(&self, v: Point) -> Point {
+        let m = &self.m;
+        Point { x: m[0] * v.x + m[3] * v.y, y: m[1] * v.x + m[4] * v.y }
+    }
+
+    pub fn determinant(&self) -> f32 {
+        let m = &self.m;
+        m[0] * (m[4] * m[8] - m[7] * m[5]) - m[3] * (m[1] * m[8] - m[7] * m[2]) + m[6] * (m[1] * m[5] - m[4] * m[2])
+    }
+
+    /// Inverse transform, `None` when the matrix is singular, e.g. scaled by zero.
+    pub fn invert(&self) -> Option<Mat3> {
+        let det = self.determinant();
+        if det == 0.0 || !det.is_finite() {
+            return None;
+        }
+        let m = &self.m;
+        let cofactors = [
+            m[4] * m[8] - m[5] * m[7], m[2] * m[7] - m[1] * m[8], m[1] * m[5] - m[2] * m[4],
+            m[5] * m[6] - m[3] * m[8], m[0] * m[8] - m[2] * m[6], m[2] * m[3] - m[0] * m[5],
+            m[3] * m[7] - m[4] * m[6], m[1] * m[6] - m[0] * m[7], m[0] * m[4] - m[1] * m[3],
+        ];
+        let mut inverse = [0_f32; 9];
+        for (value, cofactor) in inverse.iter_mut().zip(cofactors.iter()) {
+            *value = cofactor / det;
+        }
+        Some(Mat3 { m: inverse })
+    }
+}
+
+/// `a * b` applies `b` first, then `a`.
+impl ops::Mul<Mat3> for Mat3 {
+    type Output = Mat3;
+
+    fn mul(self, other: Mat3) -> Mat3 {
+        let a = &self.m;
+        let b = &other.m;
+        let mut m = [0_f32; 9];
+        for column in 0..3 {
+            for row in 0..3 {
+                m[column * 3 + row] = (0..3).map(|k| a[k * 3 + row] * b[column * 3 + k]).sum();
+            }
+        }
+        Mat3 { m }
+    }
+}
@@ -0,0 +1,100 @@
+use wasm_bindgen::JsValue;
+use web_sys::{HtmlCanvasElement, WebGl2RenderingContext, WebGlBuffer, WebGlFramebuffer, WebGlProgram, WebGlRenderingContext,
+              WebGlShader, WebGlTexture, WebGlUniformLocation};
+
+/// WebGL 1 or WebGL 2 context. Object types and constants are shared by both versions,
+/// so the renderer uses `WebGlRenderingContext` constants with either of them.
+pub enum GlContext {
+    WebGl1(WebGlRenderingContext),
+    WebGl2(WebGl2RenderingContext),
+}
+
+/// Generates methods calling the same-named method of whichever context is wrapped.
+macro_rules! dispatch {
+    ($(fn $name:ident(&self $(, $arg:ident: $ty:ty)*) $(-> $ret:ty)?;)*) => {
+        impl GlContext {
+            $(
+                // signatures mirror web-sys
+                #[allow(clippy::too_many_arguments)]
+                pub fn $name(&self $(, $arg: $ty)*) $(-> $ret)? {
+                    match self {
+                        GlContext::WebGl1(gl) => gl.$name($($arg),*),
+                        GlContext::WebGl2(gl) => gl.$name($($arg),*),
+                    }
+                }
+            )*
+        }
+    };
+}
+
+dispatch! {
+    fn active_texture(&self, texture: u32);
+    fn attach_shader(&self, program: &WebGlProgram, shader: &WebGlShader);
+    fn bind_attrib_location(&self, program: &WebGlProgram, index: u32, name: &str);
+    fn bind_buffer(&self, target: u32, buffer: Option<&WebGlBuffer>);
+    fn bind_framebuffer(&self, target: u32, framebuffer: Option<&WebGlFramebuffer>);
+    fn bind_texture(&self, target: u32, texture: Option<&WebGlTexture>);
+    fn blend_func(&self, sfactor: u32, dfactor: u32);
+    fn buffer_data_with_i32(&self, target: u32, size: i32, usage: u32);
+    fn buffer_data_with_u8_array(&self, target: u32, data: &[u8], usage: u32);
+    fn buffer_sub_data_with_i32_and_u8_array(&self, target: u32, offset: i32, data: &[u8]);
+    fn canvas(&self) -> Option<js_sys::Object>;
+    fn clear(&self, mask: u32);
+    fn clear_color(&self, red: f32, green: f32, blue: f32, alpha: f32);
+    fn compile_shader(&self, shader: &WebGlShader);
+    fn create_buffer(&self) -> Option<WebGlBuffer>;
+    fn create_framebuffer(&self) -> Option<WebGlFramebuffer>;
+    fn create_program(&self) -> Option<WebGlProgram>;
+    fn create_shader(&self, shader_type: u32) -> Option<WebGlShader>;
+    fn create_texture(&self) -> Option<WebGlTexture>;
+    fn delete_buffer(&self, buffer: Option<&WebGlBuffer>);
+    fn delete_framebuffer(&self, framebuffer: Option<&WebGlFramebuffer>);
+    fn delete_program(&self, program: Option<&WebGlProgram>);
+    fn delete_shader(&self, shader: Option<&WebGlShader>);
+    fn delete_texture(&self, texture: Option<&WebGlTexture>);
+    fn disable(&self, cap: u32);
+    fn draw_arrays(&self, mode: u32, first: i32, count: i32);
+    fn enable(&self, cap: u32);
+    fn enable_vertex_attrib_array(&self, index: u32);
+    fn framebuffer_texture_2d(&self, target: u32, attachment: u32, textarget: u32, texture: Option<&WebGlTexture>, level: i32);
+    fn generate_mipmap(&self, target: u32);
+    fn get_parameter(&self, pname: u32) -> Result<JsValue, JsValue>;
+    fn get_program_info_log(&self, program: &WebGlProgram) -> Option<String>;
+    fn get_program_parameter(&self, program: &WebGlProgram, pname: u32) -> JsValue;
+    fn get_shader_info_log(&self, shader: &WebGlShader) -> Option<String>;
+    fn get_shader_parameter(&self, shader: &WebGlShader, pname: u32) -> JsValue;
+    fn get_uniform_location(&self, program: &WebGlProgram, name: &str) -> Option<WebGlUniformLocation>;
+    fn is_context_lost(&self) -> bool;
+    fn link_program(&self, program: &WebGlProgram);
+    fn pixel_storei(&self, pname: u32, param: i32);
+    fn shader_source(&self, shader: &WebGlShader, source: &str);
+    fn tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_u8_array(
+        &self, target: u32, level: i32, internalformat: i32, width: i32, height: i32, border: i32,
+        format: u32, type_: u32, pixels: Option<&[u8]>) -> Result<(), JsValue>;
+    fn tex_parameteri(&self, target: u32, pname: u32, param: i32);
+    fn tex_sub_image_2d_with_i32_and_i32_and_u32_and_type_and_opt_u8_array(
+        &self, target: u32, level: i32, xoffset: i32, yoffset: i32, width: i32, height: i32,
+        format: u32, type_: u32, pixels: Option<&[u8]>) -> Result<(), JsValue>;
+    fn uniform1f(&self, location: Option<&WebGlUniformLocation>, x: f32);
+    fn uniform1i(&self, location: Option<&WebGlUniformLocation>, x: i32);
+    fn uniform2f(&self, location: Option<&WebGlUniformLocation>, x: f32, y: f32);
+    fn uniform4f(&self, location: Option<&WebGlUniformLocation>, x: f32, y: f32, z: f32, w: f32);
+    fn uniform4fv_with_f32_array(&self, location: Option<&WebGlUniformLocation>, data: &[f32]);
+    fn uniform_matrix3fv_with_f32_array(&self, location: Option<&WebGlUniformLocation>, transpose: bool, data: &[f32]);
+    fn use_program(&self, program: Option<&WebGlProgram>);
+    fn vertex_attrib_pointer_with_i32(&self, index: u32, size: i32, type_: u32, normalized: bool, stride: i32, offset: i32);
+    fn viewport(&self, x: i32, y: i32, width: i32, height: i32);
+}
+
+impl GlContext {
+    /// `texImage2D` with a canvas source, named differently in the two versions.
+    pub fn tex_image_2d_with_canvas(&self, target: u32, level: i32, internalformat: i32, format: u32, type_: u32,
+                                    canvas: &HtmlCanvasElement) -> Result<(), JsValue> {
+        match self {
+            GlContext::WebGl1(gl) =>
+                gl.tex_image_2d_with_u32_and_u32_and_canvas(target, level, internalformat, format, type_, canvas),
+            GlContext::WebGl2(gl) =>
+                gl.tex_image_2d_with_u32_and_u32_and_html_canvas_element(target, level, internalformat, format, type_, canvas),
+        }
+    }
+}
@@ -0,0 +1,59 @@
+/// Named group of sprites. Layers are drawn in the order they are declared, whatever the
+/// order of sprites, and each of them can be hidden as a whole.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Layer {
+    Background,
+    /// Scene content, e.g. snowflakes.
+    Flakes,
+    /// UI drawn above everything else, e.g. the loading banner.
+    Overlay,
+}
+
+impl Layer {
+    pub const ALL: [Layer; 3] = [Layer::Background, Layer::Flakes, Layer::Overlay];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Layer::Background => "background",
+            Layer::Flakes => "flakes",
+            Layer::Overlay => "overlay",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Layer> {
+        Layer::ALL.iter().copied().find(|layer| layer.name() == name)
+    }
+
+    fn bit(self) -> u8 {
+        1 << self as u8
+    }
+}
+
+/// Set of layers, e.g. the visible ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LayerSet {
+    bits: u8,
+}
+
+impl LayerSet {
+    pub fn all() -> LayerSet {
+        LayerSet { bits: Layer::ALL.iter().fold(0, |bits, layer| bits | layer.bit()) }
+    }
+
+    pub fn empty() -> LayerSet {
+        LayerSet { bits: 0 }
+    }
+
+    pub fn contains(&self, layer: Layer) -> bool {
+        self.bits & layer.bit() != 0
+    }
+
+    /// Adds or removes `layer`.
+    pub fn set(&mut self, layer: Layer, present: bool) {
+        if present {
+            self.bits |= layer.bit();
+        } else {
+            self.bits &= !layer.bit();
+        }
+    }
+}
@@ -1,50 +1,97 @@
 use std::cell::RefMut;
+use std::rc::Rc;
+use std::cell::RefCell;
 
 use wasm_bindgen::JsCast;
 use wasm_bindgen::prelude::*;
 use wasm_bindgen_futures::{JsFuture, spawn_local};
-use web_sys::{console, Crypto, Response, Blob, ImageBitmap, HtmlCanvasElement, MouseEvent, TouchEvent, CanvasRenderingContext2d};
+use web_sys::{console, Response, Blob, ImageBitmap, HtmlCanvasElement, MouseEvent, TouchEvent, WheelEvent, AddEventListenerOptions, CanvasRenderingContext2d,
+              UrlSearchParams, Window};
 use js_sys::{Date, Number};
 
-use logger::{log_debug, log_info};
-use renderer::{Renderer, TextureAtlas, Projection, Sprite};
+use logger::{log_debug, log_info, log_warn};
+use background::{Background, ImageFit};
+use camera::{client_to_screen, Camera};
+use layer::Layer;
+use material::{MaterialId, Shader, UniformValue, DEFAULT_MATERIAL};
+use postprocess::PostEffect;
+use renderer::{BlendMode, Renderer, RenderBackend, TextureAtlas, Projection, Sprite};
+use cpu_renderer::CpuRenderer;
+use texture::TextureSource;
 use resource_manager::ImageLoader;
-use wasm_bindgen::__rt::std::rc::Rc;
-use wasm_bindgen::__rt::core::cell::RefCell;
+use simulation::Simulation;
+use random::{CryptoRng, Rng, XorShift};
+use lifecycle::{ContextEvent, ContextLifecycle, ContextState, RECREATE_ATTEMPTS};
 use crate::geom::Point;
-use wasm_bindgen::__rt::core::f32::consts::PI;
 use crate::logger::log_error;
-use crate::Stage::{LOADING, SNOWFLAKES};
-use js_sys::Math::cos;
+use crate::Stage::{Loading, Snowflakes};
 
+pub mod background;
+pub mod buffers;
+pub mod camera;
+pub mod cpu_renderer;
 mod logger;
-mod geom;
-mod renderer;
-mod resource_manager;
+pub mod packer;
+pub mod geom;
+pub mod layer;
+mod gl_context;
+pub mod lifecycle;
+pub mod material;
+pub mod postprocess;
+pub mod random;
+pub mod renderer;
+pub mod resource_manager;
+pub mod scene;
+pub mod texture;
+pub mod simulation;
+
+/// Night sky behind the flakes with the `background=night` URL parameter.
+const NIGHT_SKY: Background = Background::VerticalGradient {
+    top: [0.01, 0.02, 0.08, 1.0],
+    bottom: [0.08, 0.12, 0.25, 1.0],
+};
 
 const IMAGES_URL: [&str; 6] = ["/img/snowflake0.png", "/img/snowflake1.png", "/img/snowflake2.png",
     "/img/snowflake3.png", "/img/snowflake4.png", "/img/snowflake5.png"];
 
 #[derive(Clone, Copy)]
 enum Stage {
-    LOADING, SNOWFLAKES
+    Loading, Snowflakes
 }
 
 struct RendererContext {
-    renderer: Renderer,
+    renderer: Box<dyn RenderBackend>,
     atlas: TextureAtlas,
+    /// Sources of `atlas`, kept to upload it again after the context is restored.
+    atlas_sources: Vec<TextureSource>,
     projection: Projection,
+    camera: Camera,
+}
+
+/// Materials snowflakes are drawn with besides the default one.
+struct FlakeMaterials {
+    tinted: MaterialId,
+    glowing: MaterialId,
+    blurred: MaterialId,
 }
 
 struct SceneContext {
     stage: Stage,
     renderer_context: RendererContext,
-    images: Vec<ImageBitmap>,
+    images: Vec<TextureSource>,
+    /// Image drawn behind the flakes, added to the atlas after `images`.
+    backdrop: Option<TextureSource>,
     sprites: Vec<Sprite>,
+    simulation: Simulation,
+    /// Only with the `materials` URL parameter, otherwise flakes keep the default material.
+    flake_materials: Option<FlakeMaterials>,
+    seed: u64,
+    rng: XorShift,
     mouse_pos: Option<Point>,
-    last_render_mouse_pos: Option<Point>,
-    wind: Point,
-    last_render_time: u64
+    last_render_time: u64,
+    lifecycle: ContextLifecycle,
+    /// Whether an animation frame is requested, the loop stops while the context is lost.
+    animating: bool
 }
 
 
@@ -61,37 +108,59 @@ async fn run_logged() {
     }
 }
 
-async fn run() -> Result<(), (JsValue)> {
+async fn run() -> Result<(), JsValue> {
     let window = web_sys::window().unwrap();
 
-    let mut context = SceneContext {
-        stage: LOADING,
-        renderer_context: create_renderer()?,
+    let mut renderer_context = create_renderer()?;
+    let backdrop_url = match url_background() {
+        Some(param) if param == "transparent" => {
+            renderer_context.renderer.set_background(Background::Transparent);
+            None
+        }
+        Some(param) if param == "night" => {
+            renderer_context.renderer.set_background(NIGHT_SKY);
+            None
+        }
+        // black, also behind a backdrop until it is loaded
+        param => param
+    };
+    let flake_materials = if url_flag("materials") {
+        Some(create_flake_materials(renderer_context.renderer.as_mut())?)
+    } else {
+        None
+    };
+    let simulation = Simulation::create(renderer_context.projection.canvas_width as f32,
+                                        renderer_context.projection.canvas_height as f32);
+    let seed = match url_seed() {
+        Some(seed) => seed,
+        None => CryptoRng::create()?.next_u64()
+    };
+    log_info(format!("Scene seed {}, add ?seed={} to the URL to reproduce it", seed, seed).as_str());
+    let context = SceneContext {
+        stage: Loading,
+        renderer_context,
         sprites: Vec::new(),
         images: Vec::with_capacity(IMAGES_URL.len()),
+        backdrop: None,
+        simulation,
+        flake_materials,
+        seed,
+        rng: XorShift::create(seed),
         mouse_pos: None,
-        last_render_mouse_pos: None,
-        wind: Point { x: 0.0, y: 0.0 },
-        last_render_time: 0 };
+        last_render_time: 0,
+        lifecycle: ContextLifecycle::create(),
+        animating: false };
     let context_rc = Rc::new(RefCell::new(context));
-    create_loading_scene(context_rc.clone().borrow_mut());
+    create_loading_scene(context_rc.borrow_mut())?;
     request_animation_frame(context_rc.clone())?;
     {
         let context_rc = context_rc.clone();
         let closure = Closure::wrap(Box::new(move |_: web_sys::Event| {
-            let stage = context_rc.borrow().stage;
-            match {create_renderer()} {
-                Ok(renderer_context) => {
-                    context_rc.clone().borrow_mut().renderer_context = renderer_context;
-                    match stage {
-                        LOADING => create_loading_scene(context_rc.clone().borrow_mut()),
-                        SNOWFLAKES => create_scene(context_rc.clone().borrow_mut())
-                    };
-                },
-                Err(e) => log_error(format!("Filed to create renderer, {:?}", &e).as_str())
-            };
+            if let Err(e) = resize_scene(context_rc.borrow_mut()) {
+                log_error(format!("Failed to resize scene, {:?}", &e).as_str());
+            }
         }) as Box<dyn Fn(_)>);
-        window.add_event_listener_with_callback("resize", closure.as_ref().unchecked_ref());
+        window.add_event_listener_with_callback("resize", closure.as_ref().unchecked_ref())?;
         closure.forget();
     }
     {
@@ -99,7 +168,7 @@ async fn run() -> Result<(), (JsValue)> {
         let closure = Closure::wrap(Box::new(move |e: MouseEvent| {
             mouse_move_handler(context_rc.borrow_mut(), e);
         }) as Box<dyn Fn(MouseEvent)>);
-        window.add_event_listener_with_callback("mousemove", closure.as_ref().unchecked_ref());
+        window.add_event_listener_with_callback("mousemove", closure.as_ref().unchecked_ref())?;
         closure.forget();
     }
     {
@@ -107,52 +176,225 @@ async fn run() -> Result<(), (JsValue)> {
         let closure = Closure::wrap(Box::new(move |e: TouchEvent| {
             touch_move_handler(context_rc.borrow_mut(), e);
         }) as Box<dyn Fn(TouchEvent)>);
-        window.add_event_listener_with_callback("touchstart", closure.as_ref().unchecked_ref());
-        window.add_event_listener_with_callback("touchmove", closure.as_ref().unchecked_ref());
-        window.add_event_listener_with_callback("touchend", closure.as_ref().unchecked_ref());
+        window.add_event_listener_with_callback("touchstart", closure.as_ref().unchecked_ref())?;
+        window.add_event_listener_with_callback("touchmove", closure.as_ref().unchecked_ref())?;
+        window.add_event_listener_with_callback("touchend", closure.as_ref().unchecked_ref())?;
+        closure.forget();
+    }
+    {
+        let context_rc = context_rc.clone();
+        let closure = Closure::wrap(Box::new(move |e: WheelEvent| {
+            wheel_handler(context_rc.borrow_mut(), e);
+        }) as Box<dyn Fn(WheelEvent)>);
+        let options = AddEventListenerOptions::new();
+        // the default passive listener could not prevent page scrolling
+        options.set_passive(false);
+        window.add_event_listener_with_callback_and_add_event_listener_options(
+            "wheel", closure.as_ref().unchecked_ref(), &options)?;
+        closure.forget();
+    }
+    let canvas = window.document().unwrap().get_element_by_id("canvas").unwrap();
+    {
+        let context_rc = context_rc.clone();
+        let closure = Closure::wrap(Box::new(move |e: web_sys::Event| {
+            // without preventDefault the browser never restores the context
+            e.prevent_default();
+            let state = context_rc.borrow_mut().lifecycle.handle(ContextEvent::Lost);
+            log_warn(format!("Rendering context lost, {:?}", state).as_str());
+        }) as Box<dyn Fn(_)>);
+        canvas.add_event_listener_with_callback("webglcontextlost", closure.as_ref().unchecked_ref())?;
+        closure.forget();
+    }
+    {
+        let context_rc = context_rc.clone();
+        let closure = Closure::wrap(Box::new(move |_: web_sys::Event| {
+            restore_context(context_rc.clone());
+        }) as Box<dyn Fn(_)>);
+        canvas.add_event_listener_with_callback("webglcontextrestored", closure.as_ref().unchecked_ref())?;
         closure.forget();
     }
 
     log_info("Start fetch images");
     for image in IMAGES_URL.iter() {
-        log_info(format!("Start fetch image {}", image).as_str());
-        let response = ImageLoader::fetch_image(image)?.await?;
-        let response: Response = response.dyn_into().unwrap();
-        log_info(format!("Image {} fetched", image).as_str());
-        let blob = JsFuture::from(response.blob()?).await?;
-        let blob: Blob = blob.dyn_into::<Blob>().unwrap();
-        log_info(format!("Image {} blob read", image).as_str());
-        let image_bitmap = JsFuture::from(window.create_image_bitmap_with_blob(&blob)?).await?;
-        let image_bitmap: ImageBitmap = image_bitmap.dyn_into::<ImageBitmap>().unwrap();
-        log_info(format!("Image {} decoded", image).as_str());
-        context_rc.borrow_mut().images.push(image_bitmap);
+        let image_bitmap = fetch_bitmap(&window, image).await?;
+        context_rc.borrow_mut().images.push(TextureSource::Bitmap(image_bitmap));
+    }
+    if let Some(url) = backdrop_url {
+        // the scene does without its backdrop rather than not at all
+        match fetch_bitmap(&window, &url).await {
+            Ok(image_bitmap) => context_rc.borrow_mut().backdrop = Some(TextureSource::Bitmap(image_bitmap)),
+            Err(e) => log_warn(format!("Background image {} is not available, {:?}", url, &e).as_str())
+        }
     }
 
-    create_scene(context_rc.borrow_mut());
+    create_scene(context_rc.borrow_mut())?;
     Ok(())
 }
 
-fn create_renderer() -> Result<RendererContext, JsValue> {
+async fn fetch_bitmap(window: &Window, image: &str) -> Result<ImageBitmap, JsValue> {
+    log_info(format!("Start fetch image {}", image).as_str());
+    let response = ImageLoader::fetch_image(image)?.await?;
+    let response: Response = response.dyn_into().unwrap();
+    log_info(format!("Image {} fetched", image).as_str());
+    let blob = JsFuture::from(response.blob()?).await?;
+    let blob: Blob = blob.dyn_into::<Blob>().unwrap();
+    log_info(format!("Image {} blob read", image).as_str());
+    let image_bitmap = JsFuture::from(window.create_image_bitmap_with_blob(&blob)?).await?;
+    let image_bitmap: ImageBitmap = image_bitmap.dyn_into::<ImageBitmap>().unwrap();
+    log_info(format!("Image {} decoded", image).as_str());
+    Ok(image_bitmap)
+}
+
+/// Canvas size in device pixels filling the window.
+/// Device pixels per CSS pixel of the canvas, at least 1.
+fn pixel_ratio() -> f64 {
+    web_sys::window().unwrap().device_pixel_ratio().max(1.0)
+}
+
+fn canvas_size() -> Result<(u32, u32), JsValue> {
     let window = web_sys::window().unwrap();
-    let pixel_ratio = window.device_pixel_ratio();
-    let pixel_ratio = if pixel_ratio < 1.0 { 1.0 } else { pixel_ratio };
+    let pixel_ratio = pixel_ratio();
     let window_width = window.inner_width()?;
     let window_width: Number = window_width.dyn_into::<Number>()?;
     let width = (window_width.value_of() * pixel_ratio) as u32;
     let window_height = window.inner_height()?;
     let window_height: Number = window_height.dyn_into::<Number>()?;
     let height = (window_height.value_of() * pixel_ratio) as u32;
+    log_info(format!("Canvas sizes: {}x{}, pixel ratio {}", width, height, pixel_ratio).as_str());
+    Ok((width, height))
+}
 
+fn create_renderer() -> Result<RendererContext, JsValue> {
+    let window = web_sys::window().unwrap();
+    let (width, height) = canvas_size()?;
     let document = window.document().unwrap();
     let canvas = document.get_element_by_id("canvas").unwrap();
     let canvas: HtmlCanvasElement = canvas.dyn_into::<HtmlCanvasElement>()?;
     canvas.set_width(width);
     canvas.set_height(height);
-    log_info(format!("Canvas sizes: {}x{}, pixel ratio {}", width, height, pixel_ratio).as_str());
-    let renderer = Renderer::init(&canvas)?;
+    let mut renderer: Box<dyn RenderBackend> = match Renderer::init(&canvas) {
+        Ok(renderer) => Box::new(renderer),
+        Err(e) => {
+            log_warn(format!("WebGL renderer is not available, {:?}, falling back to software rendering", &e).as_str());
+            Box::new(CpuRenderer::init(&canvas)?)
+        }
+    };
+    for layer in url_hidden_layers() {
+        renderer.set_layer_visible(layer, false);
+    }
+    if url_flag("effects") {
+        // bright flakes glow a little and the corners fade
+        renderer.set_post_effects(vec![
+            PostEffect::Bloom { threshold: 0.7, radius: 4.0, intensity: 0.5 },
+            PostEffect::Vignette { radius: 0.9, intensity: 0.4 },
+        ]);
+    }
     let atlas = TextureAtlas::empty();
     let projection = Projection::create(width, height);
-    Ok(RendererContext { renderer, atlas, projection })
+    let camera = Camera::create(width, height);
+    Ok(RendererContext { renderer, atlas, atlas_sources: Vec::new(), projection, camera })
+}
+
+fn create_flake_materials(renderer: &mut dyn RenderBackend) -> Result<FlakeMaterials, JsValue> {
+    let tint = renderer.add_shader(Shader::tint())?;
+    let glow = renderer.add_shader(Shader::glow())?;
+    let blur = renderer.add_shader(Shader::blur())?;
+    let materials = FlakeMaterials {
+        tinted: renderer.add_material(tint)?,
+        glowing: renderer.add_material(glow)?,
+        blurred: renderer.add_material(blur)?,
+    };
+    renderer.set_uniform(materials.tinted, "u_color", UniformValue::Vec4([0.8, 0.9, 1.0, 1.0]))?;
+    renderer.set_uniform(materials.glowing, "u_color", UniformValue::Vec4([0.7, 0.85, 1.0, 0.6]))?;
+    Ok(materials)
+}
+
+/// Flakes are ordered from far to near: far ones get a cold tint, near ones are out of focus and
+/// a band in between glows. Contiguous ranges keep the number of batches low.
+fn style_flakes(sprites: &mut [Sprite], materials: &FlakeMaterials) {
+    let count = sprites.len();
+    for (i, sprite) in sprites.iter_mut().enumerate() {
+        let distance = i as f32 / count as f32;
+        sprite.material = if distance < 0.3 {
+            materials.tinted
+        } else if distance >= 0.9 {
+            materials.blurred
+        } else if distance >= 0.75 {
+            materials.glowing
+        } else {
+            DEFAULT_MATERIAL
+        };
+        sprite.blend = if sprite.material == materials.glowing { BlendMode::Additive } else { BlendMode::Normal };
+    }
+}
+
+/// Replaces the atlas with one created from `sources`, deleting the textures of the old one.
+fn load_atlas(renderer_context: &mut RendererContext, sources: Vec<TextureSource>) -> Result<(), JsValue> {
+    let atlas = renderer_context.renderer.create_atlas(&sources)?;
+    let old = std::mem::replace(&mut renderer_context.atlas, atlas);
+    renderer_context.renderer.delete_atlas(old);
+    renderer_context.atlas_sources = sources;
+    log_debug(format!("Renderer resources: {:?}", renderer_context.renderer.resources()).as_str());
+    Ok(())
+}
+
+/// Delay before recreating GPU objects again after a failure.
+const RECREATE_RETRY_MS: i32 = 1000;
+
+/// Recreates GPU objects and the atlas after the context came back, then resumes rendering.
+/// Failures are retried a few times, then rendering stays paused until the next restore event.
+fn restore_context(context_rc: Rc<RefCell<SceneContext>>) {
+    let (resume, retry) = {
+        let mut context = context_rc.borrow_mut();
+        let context = &mut *context;
+        let state = context.lifecycle.handle(ContextEvent::Restored);
+        if state != ContextState::Restoring {
+            log_warn(format!("Unexpected context restore, {:?}", state).as_str());
+            return;
+        }
+        let renderer_context = &mut context.renderer_context;
+        // after a failed attempt the context is alive and its atlas textures are not gone
+        let old = std::mem::replace(&mut renderer_context.atlas, TextureAtlas::empty());
+        renderer_context.renderer.delete_atlas(old);
+        let result = renderer_context.renderer.restore().and_then(|_| {
+            renderer_context.atlas = renderer_context.renderer.create_atlas(&renderer_context.atlas_sources)?;
+            Ok(())
+        });
+        let event = match result {
+            Ok(_) => ContextEvent::Recreated,
+            Err(e) => {
+                log_error(format!("Failed to restore rendering context, {:?}", &e).as_str());
+                ContextEvent::RecreateFailed
+            }
+        };
+        let state = context.lifecycle.handle(event);
+        if state == ContextState::Active {
+            log_info("Rendering context restored");
+        } else if !context.lifecycle.should_retry() {
+            log_error(format!("Rendering stopped, the context could not be restored in {} attempts", RECREATE_ATTEMPTS).as_str());
+        }
+        context.last_render_time = 0;
+        (context.lifecycle.should_render() && !context.animating, context.lifecycle.should_retry())
+    };
+    if retry {
+        let retry_rc = context_rc.clone();
+        let closure = Closure::once_into_js(move || {
+            // a loss meanwhile brings its own restore event
+            if retry_rc.borrow().lifecycle.should_retry() {
+                restore_context(retry_rc);
+            }
+        });
+        let scheduled = web_sys::window().unwrap()
+            .set_timeout_with_callback_and_timeout_and_arguments_0(closure.unchecked_ref(), RECREATE_RETRY_MS);
+        if let Err(e) = scheduled {
+            log_error(format!("Failed to schedule restoring the context, {:?}", &e).as_str());
+        }
+    }
+    if resume {
+        if let Err(e) = request_animation_frame(context_rc) {
+            log_error(format!("Failed to request animation frame, {:?}", &e).as_str());
+        }
+    }
 }
 
 fn create_loading_scene(mut context: RefMut<SceneContext>) -> Result<(), JsValue> {
@@ -169,16 +411,14 @@ fn create_loading_scene(mut context: RefMut<SceneContext>) -> Result<(), JsValue
     canvas.set_height(sprite_height as u32);
     let context2d = canvas.get_context("2d")?.unwrap();
     let context2d = context2d.dyn_into::<CanvasRenderingContext2d>().unwrap();
-    context2d.set_fill_style(&JsValue::from("lightgray"));
+    context2d.set_fill_style_str("lightgray");
     context2d.fill_rect(0_f64, 0_f64, sprite_width as f64, sprite_height as f64);
     context2d.set_font("6em monospace");
-    context2d.set_fill_style(&JsValue::from("black"));
+    context2d.set_fill_style_str("black");
     context2d.set_text_align("center");
-    context2d.fill_text("Loading…", sprite_width as f64 * 0.5, 54_f64);
+    context2d.fill_text("Loading…", sprite_width as f64 * 0.5, 54_f64)?;
     log_info("Loading sprite ready");
-    let mut canvases: Vec<HtmlCanvasElement> = Vec::with_capacity(1);
-    canvases.push(canvas);
-    context.renderer_context.atlas = context.renderer_context.renderer.create_texture_with_canvases(&document, &canvases)?;
+    load_atlas(&mut context.renderer_context, vec![TextureSource::Canvas(canvas)])?;
     context.sprites.clear();
     context.sprites.push(Sprite {
         texture: 0,
@@ -187,159 +427,200 @@ fn create_loading_scene(mut context: RefMut<SceneContext>) -> Result<(), JsValue
         rotation: 0_f32,
         width: sprite_width,
         height: sprite_height,
-        alpha: 1_f32
+        alpha: 1_f32,
+        layer: Layer::Overlay,
+        ..Sprite::default()
     });
     log_info("Loading sprite added to scene");
     Ok(())
 }
 
 fn create_scene(mut context: RefMut<SceneContext>) -> Result<(), JsValue> {
-    let window = web_sys::window().unwrap();
-    let document = window.document().unwrap();
-    context.renderer_context.atlas = context.renderer_context.renderer.create_texture_with_images(&document, &context.images)?;
+    let context = &mut *context;
+    let mut sources = context.images.clone();
+    if let Some(backdrop) = context.backdrop.take() {
+        sources.push(backdrop.clone());
+        match load_atlas(&mut context.renderer_context, sources) {
+            Ok(_) => {
+                context.renderer_context.renderer.set_background(Background::Image { texture: context.images.len(), fit: ImageFit::Cover });
+                context.backdrop = Some(backdrop);
+            }
+            // e.g. a photo larger than an atlas page, the flakes do without it
+            Err(e) => {
+                log_warn(format!("Backdrop dropped, {:?}", e).as_str());
+                load_atlas(&mut context.renderer_context, context.images.clone())?;
+            }
+        }
+    } else {
+        load_atlas(&mut context.renderer_context, sources)?;
+    }
 
-    let width = context.renderer_context.projection.canvas_width;
-    let height = context.renderer_context.projection.canvas_height;
-    context.sprites.clear();
-    let crypto = window.crypto()?;
-    let crypto: Crypto = crypto.dyn_into::<Crypto>().unwrap();
-    let textures = context.images.len();
-    let snowflakes_quantity = (width as f32 * height as f32 * 0.0006) as usize;
-    for i in 0..snowflakes_quantity {
-        let tex_index = rand_range(&crypto, 0.0, textures as f32)? as usize;
-        let image = &context.images[tex_index];
-        let distance = i as f32 / snowflakes_quantity as f32;
-        let size = 15.0 + distance * 80.0;
-        let sprite_width = size;
-        let sprite_height = size * image.height() as f32 / image.width() as f32;
-        let position = Point {
-            x: rand_range(&crypto, -100.0, 100.0 + width as f32)?,
-            y: rand_range(&crypto, -100.0, 100.0 + height as f32)?,
-        };
-        context.sprites.push(Sprite {
-            texture: tex_index,
-            position,
-            pivot: Point { x: sprite_width * 0.5, y: sprite_height * 0.5 },
-            rotation: rand_range(&crypto, 0.0, 2.0 * PI)?,
-            width: sprite_width,
-            height: sprite_height,
-            alpha: 0.25 + distance * 0.4,
-        })
+    context.simulation.width = context.renderer_context.projection.canvas_width as f32;
+    context.simulation.height = context.renderer_context.projection.canvas_height as f32;
+    let textures: Vec<(u32, u32)> = context.images.iter().map(|image| image.size()).collect();
+    context.rng = XorShift::create(context.seed);
+    context.sprites = context.simulation.populate(&textures, &mut context.rng);
+    if let Some(materials) = &context.flake_materials {
+        style_flakes(&mut context.sprites, materials);
     }
-    context.stage = SNOWFLAKES;
+    context.stage = Snowflakes;
     Ok(())
 }
 
+/// Follows the window size keeping the renderer, its textures and the sprites.
+fn resize_scene(mut context: RefMut<SceneContext>) -> Result<(), JsValue> {
+    let (width, height) = canvas_size()?;
+    let context = &mut *context;
+    let renderer_context = &mut context.renderer_context;
+    if width == renderer_context.projection.canvas_width && height == renderer_context.projection.canvas_height {
+        return Ok(());
+    }
+    renderer_context.renderer.resize(width, height);
+    renderer_context.projection.resize(width, height);
+    renderer_context.camera.resize(width, height);
+    renderer_context.projection.set_camera(&renderer_context.camera);
+    match context.stage {
+        Loading => if let Some(sprite) = context.sprites.get_mut(0) {
+            sprite.position = Point { x: width as f32 * 0.5, y: height as f32 * 0.33 };
+        },
+        Snowflakes => {
+            let textures: Vec<(u32, u32)> = context.images.iter().map(|image| image.size()).collect();
+            context.simulation.resize(&mut context.sprites, width as f32, height as f32, &textures, &mut context.rng);
+            if let Some(materials) = &context.flake_materials {
+                style_flakes(&mut context.sprites, materials);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Pointer positions are kept in world coordinates.
+/// Pointer position in device pixels of the canvas, the camera's screen space.
+fn pointer_to_screen(client_x: i32, client_y: i32) -> Point {
+    let client = Point { x: client_x as f32, y: client_y as f32 };
+    let origin = web_sys::window().and_then(|window| window.document())
+        .and_then(|document| document.get_element_by_id("canvas"))
+        .map(|canvas| canvas.get_bounding_client_rect())
+        .map_or(Point { x: 0.0, y: 0.0 }, |rect| Point { x: rect.left() as f32, y: rect.top() as f32 });
+    client_to_screen(client, origin, pixel_ratio() as f32)
+}
+
 fn mouse_move_handler(mut context: RefMut<SceneContext>, e: MouseEvent) {
+    let context = &mut *context;
+    let camera = &context.renderer_context.camera;
     context.mouse_pos =  if e.buttons() == 1 {
-        Some(Point { x: e.client_x() as f32, y : e.client_y() as f32 })
+        Some(camera.screen_to_world(pointer_to_screen(e.client_x(), e.client_y())))
     } else { None };
     log_debug(format!("Mouse: {:?}", &context.mouse_pos).as_str());
 }
 
 fn touch_move_handler(mut context: RefMut<SceneContext>, e: TouchEvent) {
-    context.mouse_pos = match e.touches().get(0) {
-        Some(t) => Some(Point { x: t.client_x() as f32, y : t.client_y() as f32 }),
-        None => None
-    };
+    let context = &mut *context;
+    let camera = &context.renderer_context.camera;
+    context.mouse_pos = e.touches().get(0)
+        .map(|t| camera.screen_to_world(pointer_to_screen(t.client_x(), t.client_y())));
     log_debug(format!("Mouse: {:?}", &context.mouse_pos).as_str());
 }
 
-fn render_loop(mut context: RefMut<SceneContext>) {
+/// Zooms around the pointer.
+fn wheel_handler(mut context: RefMut<SceneContext>, e: WheelEvent) {
+    e.prevent_default();
+    let factor = (-e.delta_y() * 0.001).exp() as f32;
+    let renderer_context = &mut context.renderer_context;
+    renderer_context.camera.zoom_at(pointer_to_screen(e.client_x(), e.client_y()), factor);
+    renderer_context.projection.set_camera(&renderer_context.camera);
+}
+
+fn render_loop(context: RefMut<SceneContext>) {
     match context.stage {
-        LOADING => render_loop_loading(context),
-        SNOWFLAKES => render_loop_snowflakes(context)
+        Loading => render_loop_loading(context),
+        Snowflakes => render_loop_snowflakes(context)
     }
 }
 
 fn render_loop_loading(mut context: RefMut<SceneContext>) {
-    let time = Date::now() as f64;
-    match context.sprites.get_mut(0) {
-        Some(sprite) => {
-            let delta_x = 0.15_f32 * cos(time * 0.0065) as f32;
-            let delta_y = delta_x * sprite.height / sprite.width;
-            sprite.alpha = 0.85 + 0.15 * cos(time * 0.03) as f32;
-            sprite.width += delta_x * 2_f32;
-            sprite.position.x -= delta_x;
-            sprite.height += delta_y * 2_f32;
-            sprite.position.y -= delta_y;
-        }
-        None => {}
+    let time = Date::now();
+    if let Some(sprite) = context.sprites.get_mut(0) {
+        let delta_x = 0.15_f32 * (time * 0.0065).cos() as f32;
+        let delta_y = delta_x * sprite.height / sprite.width;
+        sprite.alpha = 0.85 + 0.15 * (time * 0.03).cos() as f32;
+        sprite.width += delta_x * 2_f32;
+        sprite.position.x -= delta_x;
+        sprite.height += delta_y * 2_f32;
+        sprite.position.y -= delta_y;
     }
+    let context = &mut *context;
     context.renderer_context.renderer.render(
         &context.renderer_context.projection, &context.sprites, &context.renderer_context.atlas);
 }
 
 fn render_loop_snowflakes(mut context: RefMut<SceneContext>) {
-    let height = context.renderer_context.projection.canvas_height as f32;
-    let width = context.renderer_context.projection.canvas_width as f32;
-
     let time = Date::now() as u64;
     let delta_seconds = if context.last_render_time > 0 {
         (time - context.last_render_time) as f32 / 1000_f32
     } else { 0_f32 };
     context.last_render_time = time;
 
-    let mouse_delta = match context.mouse_pos {
-        Some(p) => p - match context.last_render_mouse_pos {
-            Some(l) => l,
-            None => p
-        },
-        None => Point { x: 0.0, y: 0.0 }
-    };
-    let mouse_delta = Point { x: mouse_delta.x / width, y: mouse_delta.y / height };
-    context.last_render_mouse_pos = context.mouse_pos.clone();
-    let wind_factor = if delta_seconds > 1.0 { 0.0 } else { 1.0 - 0.5 * delta_seconds };
-    context.wind = (context.wind + mouse_delta * 350.0 * delta_seconds) * wind_factor;
-
-    let wind = context.wind.clone();
-    for i in 0..context.sprites.len() {
-        let mut sprite = &mut context.sprites[i];
-        sprite.position.x += delta_seconds * (wind.x + 0.1) * sprite.width;
-        if sprite.position.x > width + 100.0 {
-            sprite.position.x -= width + 200.0;
-        }
-        if sprite.position.x < -100.0 {
-            sprite.position.x += width + 200.0;
-        }
-        sprite.position.y += delta_seconds * (wind.y * 0.5 + 0.33) * sprite.width;
-        if sprite.position.y > height + 100.0 {
-            sprite.position.y -= height + 200.0;
-        }
-        if sprite.position.y < -100.0 {
-            sprite.position.y += height + 200.0;
-        }
-        let rotation_speed = match i % 5 {
-            0 => -0.05_f32,
-            1 => -0.025_f32,
-            2 => 0.0_f32,
-            3 => 0.025_f32,
-            _ => 0.05_f32
-        };
-        sprite.rotation += delta_seconds * PI * rotation_speed;
-        if sprite.rotation > 2.0 * PI {
-            sprite.rotation -= 2.0 * PI;
-        }
-    }
+    let context = &mut *context;
+    context.simulation.step(&mut context.sprites, delta_seconds, context.mouse_pos);
     context.renderer_context.renderer.render(
         &context.renderer_context.projection, &context.sprites, &context.renderer_context.atlas);
 }
 
 fn request_animation_frame(context: Rc<RefCell<SceneContext>>) -> Result<(), JsValue> {
+    context.borrow_mut().animating = true;
+    let context_rc = context.clone();
     let closure = Closure::wrap(Box::new(move || {
-        render_loop(context.borrow_mut());
-        request_animation_frame(context.clone());
+        if !context_rc.borrow().lifecycle.should_render() {
+            log_info("Rendering paused until the context is restored");
+            context_rc.borrow_mut().animating = false;
+            return;
+        }
+        render_loop(context_rc.borrow_mut());
+        if let Err(e) = request_animation_frame(context_rc.clone()) {
+            log_error(format!("Failed to request animation frame, {:?}", &e).as_str());
+        }
     }) as Box<dyn Fn()>);
     let window = web_sys::window().unwrap();
-    window.request_animation_frame(closure.as_ref().unchecked_ref())?;
+    if let Err(e) = window.request_animation_frame(closure.as_ref().unchecked_ref()) {
+        context.borrow_mut().animating = false;
+        return Err(e);
+    }
     closure.forget();
     Ok(())
 }
 
-fn rand_range(crypto: &Crypto, min: f32, max: f32) -> Result<f32, JsValue> {
-    let mut array = [0_u8; 4];
-    crypto.get_random_values_with_u8_array(&mut array)?;
-    let v = array[0] as f32 + (array[1] as f32 * 256_f32) + (array[2] as f32 * 65536_f32);
-    let v = v / 16777216_f32;
-    Ok(min + (max - min) * v)
-}
\ No newline at end of file
+/// Parameters of the page URL.
+fn url_params() -> Option<UrlSearchParams> {
+    let search = web_sys::window()?.location().search().ok()?;
+    UrlSearchParams::new_with_str(&search).ok()
+}
+
+/// Layers listed in the `hide` URL parameter, e.g. `?hide=background,overlay`.
+fn url_hidden_layers() -> Vec<Layer> {
+    match url_params().and_then(|params| params.get("hide")) {
+        Some(names) => names.split(',').filter_map(|name| {
+            let layer = Layer::from_name(name.trim());
+            if layer.is_none() {
+                log_warn(format!("Unknown layer {}", name).as_str());
+            }
+            layer
+        }).collect(),
+        None => Vec::new()
+    }
+}
+
+/// `transparent` to show the page below the canvas, `night` for a night sky gradient, or the URL
+/// of an image, e.g. a skyline photo. Without it the background is black.
+fn url_background() -> Option<String> {
+    url_params()?.get("background").filter(|value| !value.is_empty())
+}
+
+/// Whether the URL has the parameter `name`, e.g. `?effects`.
+fn url_flag(name: &str) -> bool {
+    url_params().is_some_and(|params| params.has(name))
+}
+
+fn url_seed() -> Option<u64> {
+    url_params()?.get("seed")?.parse::<u64>().ok()
+}
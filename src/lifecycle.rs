@@ -0,0 +1,83 @@
+/// State of the GPU context a renderer draws with.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ContextState {
+    /// Rendering normally.
+    Active,
+    /// The browser took the context away, nothing is rendered until it is restored.
+    Lost,
+    /// The context is back, GPU objects and atlases are being recreated.
+    Restoring,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ContextEvent {
+    /// `webglcontextlost` event.
+    Lost,
+    /// `webglcontextrestored` event.
+    Restored,
+    /// Shaders, buffers and atlases were recreated.
+    Recreated,
+    /// Recreating failed, e.g. the context was lost again meanwhile.
+    RecreateFailed,
+}
+
+/// Transitions of the context state, kept apart from the browser events so they can be tested.
+pub struct ContextLifecycle {
+    state: ContextState,
+    losses: u32,
+    /// Failed recreations since the context was last lost.
+    failures: u32,
+}
+
+/// Recreations tried for one restored context before waiting for the browser to lose it again.
+pub const RECREATE_ATTEMPTS: u32 = 3;
+
+impl ContextLifecycle {
+    pub fn create() -> ContextLifecycle {
+        ContextLifecycle { state: ContextState::Active, losses: 0, failures: 0 }
+    }
+
+    pub fn state(&self) -> ContextState {
+        self.state
+    }
+
+    /// How many times the context was lost.
+    pub fn losses(&self) -> u32 {
+        self.losses
+    }
+
+    /// Frames are rendered only with an active context.
+    pub fn should_render(&self) -> bool {
+        self.state == ContextState::Active
+    }
+
+    /// Whether recreating failed and should be tried again without waiting for a restore event,
+    /// e.g. because an atlas texture could not be created while the context is fine.
+    pub fn should_retry(&self) -> bool {
+        self.state == ContextState::Lost && self.failures > 0 && self.failures < RECREATE_ATTEMPTS
+    }
+
+    /// Applies an event and returns the new state. Events not expected in the current
+    /// state (e.g. a second restore) are ignored.
+    pub fn handle(&mut self, event: ContextEvent) -> ContextState {
+        self.state = match (self.state, event) {
+            (ContextState::Active, ContextEvent::Lost) | (ContextState::Restoring, ContextEvent::Lost) => {
+                self.losses += 1;
+                self.failures = 0;
+                ContextState::Lost
+            }
+            (ContextState::Lost, ContextEvent::Restored) => ContextState::Restoring,
+            (ContextState::Restoring, ContextEvent::Recreated) => {
+                self.failures = 0;
+                ContextState::Active
+            }
+            // retry, or wait for the next restore event
+            (ContextState::Restoring, ContextEvent::RecreateFailed) => {
+                self.failures += 1;
+                ContextState::Lost
+            }
+            (state, _) => state
+        };
+        self.state
+    }
+}
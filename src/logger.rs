@@ -1,36 +1,50 @@
+#[cfg(target_arch = "wasm32")]
 use web_sys::console;
+#[cfg(target_arch = "wasm32")]
 use wasm_bindgen::JsValue;
-use crate::logger::Level::{DEBUG, INFO, WARN, ERROR};
+use crate::logger::Level::{Debug, Info, Warn};
 
+#[allow(dead_code)]
 pub enum Level {
-    DEBUG,
-    INFO,
-    WARN,
-    ERROR
+    Debug,
+    Info,
+    Warn,
+    Error
 }
 
-const DEBUG_LEVEL: Level = INFO;
+const DEBUG_LEVEL: Level = Info;
 
 pub fn log_debug(x: &str) {
-    match DEBUG_LEVEL {
-        DEBUG => console::log_1(&JsValue::from_str((String::from("[DEBUG] ") + x).as_str())),
-        _ => {}
+    if let Debug = DEBUG_LEVEL {
+        write((String::from("[DEBUG] ") + x).as_str());
     }
 }
 
 pub fn log_info(x: &str) {
     match DEBUG_LEVEL {
-        DEBUG | INFO => console::log_1(&JsValue::from_str((String::from("[INFO] ") + x).as_str())),
+        Debug | Info => write((String::from("[INFO] ") + x).as_str()),
         _ => {}
     }
 }
 
 pub fn log_warn(x: &str) {
     match DEBUG_LEVEL {
-        DEBUG | INFO | WARN => console::log_1(&JsValue::from_str((String::from("[WARN] ") + x).as_str())),
+        Debug | Info | Warn => write((String::from("[WARN] ") + x).as_str()),
         _ => {}
     }
 }
+
 pub fn log_error(x: &str) {
-    console::log_1(&JsValue::from_str((String::from("[ERROR] ") + x).as_str()));
+    write((String::from("[ERROR] ") + x).as_str());
+}
+
+#[cfg(target_arch = "wasm32")]
+fn write(line: &str) {
+    console::log_1(&JsValue::from_str(line));
+}
+
+/// Outside of the browser (native builds and tests) log lines go to stderr.
+#[cfg(not(target_arch = "wasm32"))]
+fn write(line: &str) {
+    eprintln!("{}", line);
 }
@@ -0,0 +1,353 @@
+use crate::geom::Point;
+use crate::texture::RgbaImage;
+
+pub type ShaderId = usize;
+pub type MaterialId = usize;
+
+/// Shader and material every sprite uses unless told otherwise: the texture as is.
+pub const DEFAULT_SHADER: ShaderId = 0;
+pub const DEFAULT_MATERIAL: MaterialId = 0;
+
+/// Uniforms set by the backends for every shader, not available for declaring.
+const BUILTIN_UNIFORMS: [&str; 4] = ["u_image", "u_matrix", "u_texelSize", "u_premultiplied"];
+
+/// Floats of `Sprite::attributes`, shared by all attributes a shader declares.
+pub const ATTRIBUTE_FLOATS: usize = 4;
+
+/// Names free in GLSL ES 1.0 that WebGL 2 programs, compiled as GLSL ES 3.0, reserve: keywords,
+/// built-in functions that cannot be redeclared and the fragment output `fragColor`.
+pub const WEBGL2_RESERVED: [&str; 58] = [
+    "layout", "centroid", "flat", "smooth", "noperspective", "patch", "sample", "resource",
+    "uint", "uvec2", "uvec3", "uvec4", "mat2x2", "mat2x3", "mat2x4", "mat3x2", "mat3x3", "mat3x4",
+    "mat4x2", "mat4x3", "mat4x4", "sampler3D", "sampler2DShadow", "samplerCubeShadow",
+    "sampler2DArray", "sampler2DArrayShadow", "isampler2D", "isampler3D", "isamplerCube",
+    "isampler2DArray", "usampler2D", "usampler3D", "usamplerCube", "usampler2DArray",
+    "texture", "textureProj", "textureLod", "textureOffset", "textureGrad", "textureSize",
+    "texelFetch", "sinh", "cosh", "tanh", "asinh", "acosh", "atanh", "trunc", "round",
+    "roundEven", "modf", "isnan", "isinf", "outerProduct", "transpose", "determinant",
+    "inverse", "fragColor",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UniformValue {
+    Float(f32),
+    Vec2(Point),
+    /// E.g. an RGBA color with components from 0 to 1.
+    Vec4([f32; 4]),
+}
+
+impl UniformValue {
+    pub fn glsl_type(&self) -> &'static str {
+        match self {
+            UniformValue::Float(_) => "float",
+            UniformValue::Vec2(_) => "vec2",
+            UniformValue::Vec4(_) => "vec4",
+        }
+    }
+
+    fn same_type(&self, other: &UniformValue) -> bool {
+        self.glsl_type() == other.glsl_type()
+    }
+}
+
+/// Type of a declared attribute, a per-sprite value the vertex stage passes on unchanged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttributeType {
+    Float,
+    Vec2,
+    Vec4,
+}
+
+impl AttributeType {
+    pub fn glsl_type(&self) -> &'static str {
+        match self {
+            AttributeType::Float => "float",
+            AttributeType::Vec2 => "vec2",
+            AttributeType::Vec4 => "vec4",
+        }
+    }
+
+    /// Number of floats taken from `Sprite::attributes`.
+    pub fn size(&self) -> usize {
+        match self {
+            AttributeType::Float => 1,
+            AttributeType::Vec2 => 2,
+            AttributeType::Vec4 => 4,
+        }
+    }
+}
+
+/// Input of a software shader for one pixel.
+pub struct Fragment<'a> {
+    /// Premultiplied texture.
+    texture: &'a RgbaImage,
+    /// Interpolated texture coordinates of the pixel.
+    pub uv: Point,
+    uniforms: &'a [UniformValue],
+    /// The sprite's `attributes`, declared attributes are packed in declaration order.
+    pub attributes: [f32; ATTRIBUTE_FLOATS],
+    premultiplied: bool,
+}
+
+impl<'a> Fragment<'a> {
+    pub(crate) fn create(texture: &'a RgbaImage, uv: Point, uniforms: &'a [UniformValue],
+                         attributes: [f32; ATTRIBUTE_FLOATS], premultiplied: bool) -> Fragment<'a> {
+        Fragment { texture, uv, uniforms, attributes, premultiplied }
+    }
+
+    /// Same as `texel` in GLSL: the bilinearly filtered texture color at `uv`, premultiplied
+    /// by alpha only for sprites with `BlendMode::Premultiplied`.
+    pub fn texel(&self, uv: Point) -> [f32; 4] {
+        let [r, g, b, a] = self.texture.sample(uv.x, uv.y);
+        if self.premultiplied || a == 0.0 {
+            [r, g, b, a]
+        } else {
+            [r / a, g / a, b / a, a]
+        }
+    }
+
+    /// Same as `u_texelSize` in GLSL: size of one texture pixel in texture coordinates.
+    pub fn texel_size(&self) -> Point {
+        Point { x: 1.0 / self.texture.width as f32, y: 1.0 / self.texture.height as f32 }
+    }
+
+    /// Value of the uniform declared at `index`, as a float. 0 for another type.
+    pub fn float(&self, index: usize) -> f32 {
+        match self.uniforms.get(index) {
+            Some(UniformValue::Float(value)) => *value,
+            _ => 0.0
+        }
+    }
+
+    pub fn vec2(&self, index: usize) -> Point {
+        match self.uniforms.get(index) {
+            Some(UniformValue::Vec2(value)) => *value,
+            _ => Point { x: 0.0, y: 0.0 }
+        }
+    }
+
+    pub fn vec4(&self, index: usize) -> [f32; 4] {
+        match self.uniforms.get(index) {
+            Some(UniformValue::Vec4(value)) => *value,
+            _ => [0.0; 4]
+        }
+    }
+}
+
+/// Fragment stage of a sprite program. The vertex stage is the backend's, a shader decides the
+/// color of every pixel of a sprite from its uniforms and the sprite's declared attributes.
+#[derive(Clone)]
+pub struct Shader {
+    pub name: String,
+    /// Declared uniforms with their default values, the type of a uniform is the type of its value.
+    pub uniforms: Vec<(String, UniformValue)>,
+    /// Declared attributes, packed in declaration order into `Sprite::attributes`, at most
+    /// `ATTRIBUTE_FLOATS` floats. They vary per sprite without splitting batches.
+    pub attributes: Vec<(String, AttributeType)>,
+    /// GLSL ES 1.0 definition of `vec4 shade(vec2 uv)` and its helper functions. WebGL 2 compiles
+    /// it as GLSL ES 3.0 with `texture2D` and `texture2DProj` defined to their new names, so it
+    /// must not use `WEBGL2_RESERVED` names, other texture lookups or extensions, and must not
+    /// declare varyings or write `gl_FragColor`.
+    /// It may call `vec4 texel(vec2 uv)` and read `u_texelSize`, the declared uniforms and attributes.
+    /// Colors are straight, or premultiplied when `u_premultiplied` is 1, see `BlendMode`.
+    /// Sprite tint and alpha are applied to the result by the backend.
+    pub glsl: String,
+    /// Software equivalent of `glsl`, reading the uniforms by their declaration index.
+    pub cpu: fn(&Fragment) -> [f32; 4],
+}
+
+impl Shader {
+    /// GLSL defines naming the declared attributes as components of the vec4 `varying`.
+    pub fn attribute_defines(&self, varying: &str) -> String {
+        let mut offset = 0;
+        let mut defines = String::new();
+        for (name, kind) in &self.attributes {
+            let swizzle = &"xyzw"[offset..offset + kind.size()];
+            defines.push_str(&format!("#define {} {}.{}\n", name, varying, swizzle));
+            offset += kind.size();
+        }
+        defines
+    }
+
+    /// The texture as is.
+    pub fn textured() -> Shader {
+        Shader {
+            name: String::from("textured"),
+            uniforms: Vec::new(),
+            attributes: Vec::new(),
+            glsl: String::from("vec4 shade(vec2 uv) { return texel(uv); }"),
+            cpu: |fragment| fragment.texel(fragment.uv),
+        }
+    }
+
+    /// Texture multiplied by `u_color`.
+    pub fn tint() -> Shader {
+        Shader {
+            name: String::from("tint"),
+            uniforms: vec![(String::from("u_color"), UniformValue::Vec4([1.0; 4]))],
+            attributes: Vec::new(),
+            glsl: String::from("vec4 shade(vec2 uv) { return texel(uv) * u_color; }"),
+            cpu: |fragment| {
+                let color = fragment.vec4(0);
+                let mut texel = fragment.texel(fragment.uv);
+                for (c, value) in texel.iter_mut().enumerate() {
+                    *value *= color[c];
+                }
+                texel
+            },
+        }
+    }
+
+    /// Halo of `u_color` around opaque pixels, `u_radius` texels wide and `u_strength` intense.
+    pub fn glow() -> Shader {
+        Shader {
+            name: String::from("glow"),
+            uniforms: vec![
+                (String::from("u_color"), UniformValue::Vec4([1.0; 4])),
+                (String::from("u_radius"), UniformValue::Float(2.0)),
+                (String::from("u_strength"), UniformValue::Float(1.5)),
+            ],
+            attributes: Vec::new(),
+            glsl: format!("{}
+vec4 shade(vec2 uv) {{
+  vec4 c = texel(uv);
+  float halo = min(box_average(uv, u_radius).a * u_strength, 1.0);
+  return vec4(mix(u_color.rgb, c.rgb, c.a), max(c.a, halo * u_color.a));
+}}", BOX_AVERAGE_GLSL),
+            cpu: |fragment| {
+                let color = fragment.vec4(0);
+                let c = fragment.texel(fragment.uv);
+                let halo = (box_average(fragment, fragment.float(1))[3] * fragment.float(2)).min(1.0);
+                let mix = |a: f32, b: f32| a * (1.0 - c[3]) + b * c[3];
+                [mix(color[0], c[0]), mix(color[1], c[1]), mix(color[2], c[2]), c[3].max(halo * color[3])]
+            },
+        }
+    }
+
+    /// Box blur over a square `2 * u_radius` texels wide. The radius should stay within the atlas
+    /// padding, otherwise neighbouring textures bleed in.
+    pub fn blur() -> Shader {
+        Shader {
+            name: String::from("blur"),
+            uniforms: vec![(String::from("u_radius"), UniformValue::Float(1.5))],
+            attributes: Vec::new(),
+            glsl: format!("{}
+vec4 shade(vec2 uv) {{ return box_average(uv, u_radius); }}", BOX_AVERAGE_GLSL),
+            cpu: |fragment| box_average(fragment, fragment.float(0)),
+        }
+    }
+}
+
+static BOX_AVERAGE_GLSL: &str = "vec4 box_average(vec2 uv, float radius) {
+  vec4 sum = vec4(0.0);
+  for (int y = -1; y <= 1; y++) {
+    for (int x = -1; x <= 1; x++) { sum += texel(uv + vec2(float(x), float(y)) * radius * u_texelSize); }
+  }
+  return sum / 9.0;
+}";
+
+/// Average of 3x3 texels `radius` texels apart, the software version of `box_average`.
+fn box_average(fragment: &Fragment, radius: f32) -> [f32; 4] {
+    let step = fragment.texel_size() * radius;
+    let mut sum = [0_f32; 4];
+    for y in -1..=1 {
+        for x in -1..=1 {
+            let texel = fragment.texel(fragment.uv + Point { x: x as f32 * step.x, y: y as f32 * step.y });
+            for (c, value) in sum.iter_mut().enumerate() {
+                *value += texel[c];
+            }
+        }
+    }
+    sum.map(|value| value / 9.0)
+}
+
+/// A shader with values of its uniforms.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Material {
+    pub shader: ShaderId,
+    /// Uniform values in the shader's declaration order.
+    pub values: Vec<UniformValue>,
+}
+
+/// Shaders and materials of a backend. Ids are indices and stay valid for the backend's lifetime,
+/// also across a context loss, after which programs are compiled again from the same shaders.
+pub struct Materials {
+    shaders: Vec<Shader>,
+    materials: Vec<Material>,
+}
+
+impl Materials {
+    /// Library with the textured shader and its material, `DEFAULT_SHADER` and `DEFAULT_MATERIAL`.
+    pub fn create() -> Materials {
+        let mut materials = Materials { shaders: Vec::new(), materials: Vec::new() };
+        materials.add_shader(Shader::textured()).unwrap();
+        materials.add_material(DEFAULT_SHADER).unwrap();
+        materials
+    }
+
+    /// Checks the uniform and attribute declarations and `WEBGL2_RESERVED` names, and stores the shader. Backends compile it before,
+    /// see `RenderBackend::add_shader`.
+    pub fn add_shader(&mut self, shader: Shader) -> Result<ShaderId, String> {
+        for (index, (name, _)) in shader.uniforms.iter().enumerate() {
+            if BUILTIN_UNIFORMS.contains(&name.as_str()) {
+                return Err(format!("Shader {}: uniform {} is built in", shader.name, name));
+            }
+            if shader.uniforms[..index].iter().any(|(other, _)| other == name) {
+                return Err(format!("Shader {}: uniform {} is declared twice", shader.name, name));
+            }
+        }
+        for (index, (name, _)) in shader.attributes.iter().enumerate() {
+            if BUILTIN_UNIFORMS.contains(&name.as_str()) || shader.uniforms.iter().any(|(uniform, _)| uniform == name)
+                || shader.attributes[..index].iter().any(|(other, _)| other == name) {
+                return Err(format!("Shader {}: attribute {} is declared twice or built in", shader.name, name));
+            }
+        }
+        let code = shader.glsl.lines().map(|line| line.split("//").next().unwrap_or(""));
+        let words = code.flat_map(|line| line.split(|c: char| !c.is_ascii_alphanumeric() && c != '_'));
+        let declared = shader.uniforms.iter().map(|(name, _)| name.as_str())
+            .chain(shader.attributes.iter().map(|(name, _)| name.as_str()));
+        if let Some(word) = words.chain(declared).find(|word| WEBGL2_RESERVED.contains(word)) {
+            return Err(format!("Shader {}: {} is reserved in WebGL 2", shader.name, word));
+        }
+        let size: usize = shader.attributes.iter().map(|(_, kind)| kind.size()).sum();
+        if size > ATTRIBUTE_FLOATS {
+            return Err(format!("Shader {}: attributes take {} floats, at most {} fit", shader.name, size, ATTRIBUTE_FLOATS));
+        }
+        self.shaders.push(shader);
+        Ok(self.shaders.len() - 1)
+    }
+
+    /// New material of `shader` with the default uniform values.
+    pub fn add_material(&mut self, shader: ShaderId) -> Result<MaterialId, String> {
+        if self.shaders.get(shader).is_none() {
+            return Err(format!("Shader {} does not exist", shader));
+        }
+        let values = self.shaders[shader].uniforms.iter().map(|(_, value)| *value).collect();
+        self.materials.push(Material { shader, values });
+        Ok(self.materials.len() - 1)
+    }
+
+    pub fn set_uniform(&mut self, material: MaterialId, name: &str, value: UniformValue) -> Result<(), String> {
+        let entry = self.materials.get_mut(material).ok_or_else(|| format!("Material {} does not exist", material))?;
+        let shader = &self.shaders[entry.shader];
+        let index = shader.uniforms.iter().position(|(uniform, _)| uniform == name)
+            .ok_or_else(|| format!("Shader {} has no uniform {}", shader.name, name))?;
+        if !shader.uniforms[index].1.same_type(&value) {
+            return Err(format!("Uniform {} of shader {} is a {}", name, shader.name, shader.uniforms[index].1.glsl_type()));
+        }
+        entry.values[index] = value;
+        Ok(())
+    }
+
+    pub fn shader(&self, id: ShaderId) -> Option<&Shader> {
+        self.shaders.get(id)
+    }
+
+    pub fn shaders(&self) -> &[Shader] {
+        &self.shaders
+    }
+
+    pub fn material(&self, id: MaterialId) -> Option<&Material> {
+        self.materials.get(id)
+    }
+}
@@ -0,0 +1,140 @@
+use std::fmt;
+
+/// Atlas packing parameters.
+#[derive(Debug, Clone, Copy)]
+pub struct AtlasConfig {
+    /// Maximum width and height of a page, usually `MAX_TEXTURE_SIZE`.
+    pub max_size: u32,
+    /// Empty pixels between neighbouring items, keeps mipmaps from mixing them.
+    pub padding: u32,
+    /// Pixels around every item filled with copies of its edge, so bilinear filtering
+    /// at the item border does not sample its neighbours.
+    pub extrusion: u32,
+    /// Maximum number of pages the items may spill into.
+    pub max_pages: usize,
+}
+
+impl AtlasConfig {
+    pub fn create(max_size: u32) -> AtlasConfig {
+        AtlasConfig { max_size, padding: 2, extrusion: 1, max_pages: 8 }
+    }
+}
+
+/// Position of an item image (without extrusion) on its page.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PackedItem {
+    pub page: usize,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Result of packing: items in the order of the given sizes, and page sizes (powers of 2).
+#[derive(Debug)]
+pub struct Packing {
+    pub items: Vec<PackedItem>,
+    pub pages: Vec<(u32, u32)>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum PackError {
+    /// Item `index` does not fit even into an empty page.
+    TooLarge { index: usize, width: u32, height: u32, max_size: u32 },
+    /// Items need more pages than allowed.
+    TooManyPages { max_pages: usize },
+}
+
+impl fmt::Display for PackError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PackError::TooLarge { index, width, height, max_size } =>
+                write!(f, "Texture {} of {}x{} does not fit into {}x{} atlas page", index, width, height, max_size, max_size),
+            PackError::TooManyPages { max_pages } =>
+                write!(f, "Textures do not fit into {} atlas page(s)", max_pages),
+        }
+    }
+}
+
+struct Shelf {
+    y: u32,
+    height: u32,
+    width: u32,
+}
+
+struct Page {
+    shelves: Vec<Shelf>,
+    height: u32,
+}
+
+impl Page {
+    /// Places a cell on the first shelf with enough room, opening a new shelf if needed.
+    fn place(&mut self, width: u32, height: u32, config: &AtlasConfig) -> Option<(u32, u32)> {
+        for shelf in self.shelves.iter_mut() {
+            let x = if shelf.width == 0 { 0 } else { shelf.width + config.padding };
+            if shelf.height >= height && x + width <= config.max_size {
+                shelf.width = x + width;
+                return Some((x, shelf.y));
+            }
+        }
+        let y = if self.shelves.is_empty() { 0 } else { self.height + config.padding };
+        if y + height > config.max_size {
+            return None;
+        }
+        self.shelves.push(Shelf { y, height, width });
+        self.height = y + height;
+        Some((0, y))
+    }
+
+    fn size(&self) -> (u32, u32) {
+        let width = self.shelves.iter().map(|shelf| shelf.width).max().unwrap_or(0);
+        (make_power_2(width), make_power_2(self.height))
+    }
+}
+
+/// Shelf packing: items are sorted by height and put left to right on shelves,
+/// spilling into a new page when the current ones are full.
+pub fn pack(sizes: &[(u32, u32)], config: &AtlasConfig) -> Result<Packing, PackError> {
+    let mut order: Vec<usize> = (0..sizes.len()).collect();
+    order.sort_by(|a, b| sizes[*b].1.cmp(&sizes[*a].1).then(sizes[*b].0.cmp(&sizes[*a].0)));
+
+    let mut pages: Vec<Page> = Vec::new();
+    let mut items = vec![PackedItem { page: 0, x: 0, y: 0, width: 0, height: 0 }; sizes.len()];
+    for index in order {
+        let (width, height) = sizes[index];
+        let cell_width = width + 2 * config.extrusion;
+        let cell_height = height + 2 * config.extrusion;
+        if cell_width > config.max_size || cell_height > config.max_size {
+            return Err(PackError::TooLarge { index, width, height, max_size: config.max_size });
+        }
+        let mut placed = None;
+        for (page_index, page) in pages.iter_mut().enumerate() {
+            if let Some((x, y)) = page.place(cell_width, cell_height, config) {
+                placed = Some((page_index, x, y));
+                break;
+            }
+        }
+        let (page, x, y) = match placed {
+            Some(p) => p,
+            None => {
+                if pages.len() >= config.max_pages {
+                    return Err(PackError::TooManyPages { max_pages: config.max_pages });
+                }
+                let mut page = Page { shelves: Vec::new(), height: 0 };
+                let (x, y) = page.place(cell_width, cell_height, config).unwrap();
+                pages.push(page);
+                (pages.len() - 1, x, y)
+            }
+        };
+        items[index] = PackedItem { page, x: x + config.extrusion, y: y + config.extrusion, width, height };
+    }
+    Ok(Packing { items, pages: pages.iter().map(|page| page.size()).collect() })
+}
+
+fn make_power_2(v: u32) -> u32 {
+    let mut p = 1_u32;
+    while p < v {
+        p *= 2;
+    }
+    p
+}
@@ -0,0 +1,242 @@
+use crate::layer::Layer;
+use crate::texture::RgbaImage;
+
+/// Largest blur radius in pixels, GLSL loops need a constant bound.
+pub const MAX_BLUR_RADIUS: f32 = 8.0;
+
+/// Distance of a corner from the centre in `Vignette` units.
+const CORNER: f32 = std::f32::consts::SQRT_2;
+
+/// Full-screen effect applied to rendered pixels, see `RenderBackend::set_post_effects`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PostEffect {
+    /// Glow around parts brighter than `threshold`, from 0 to 1: they are blurred over `radius`
+    /// pixels and added `intensity` times.
+    Bloom { threshold: f32, radius: f32, intensity: f32 },
+    /// Gaussian blur over `radius` pixels, e.g. to put a layer out of focus.
+    Blur { radius: f32 },
+    /// Darkens towards the corners. `radius` is where darkening starts, 0 at the centre and 1 in
+    /// the middle of the edges, `intensity` how dark the corners get, from 0 to 1.
+    Vignette { radius: f32, intensity: f32 },
+    /// Multiplies colors by `tint`, scales saturation and contrast around gray and adds `brightness`.
+    /// Neutral with brightness 0 and everything else 1.
+    ColorGrade { brightness: f32, contrast: f32, saturation: f32, tint: [f32; 3] },
+}
+
+impl PostEffect {
+    /// Full-screen passes the effect is drawn with.
+    pub fn passes(&self) -> Vec<PostPass> {
+        match *self {
+            PostEffect::Bloom { threshold, radius, intensity } => {
+                let radius = radius.clamp(0.0, MAX_BLUR_RADIUS);
+                vec![PostPass::Blur { horizontal: true, radius, threshold: threshold.clamp(0.0, 1.0) },
+                     PostPass::Blur { horizontal: false, radius, threshold: 0.0 },
+                     PostPass::Combine { intensity }]
+            }
+            PostEffect::Blur { radius } => {
+                let radius = radius.clamp(0.0, MAX_BLUR_RADIUS);
+                vec![PostPass::Blur { horizontal: true, radius, threshold: 0.0 },
+                     PostPass::Blur { horizontal: false, radius, threshold: 0.0 }]
+            }
+            PostEffect::Vignette { radius, intensity } =>
+                vec![PostPass::Vignette { radius: radius.clamp(0.0, CORNER - 0.01), intensity }],
+            PostEffect::ColorGrade { brightness, contrast, saturation, tint } =>
+                vec![PostPass::ColorGrade { brightness, contrast, saturation, tint }],
+        }
+    }
+}
+
+/// Effects of the whole frame and of every layer, each applied in order.
+#[derive(Debug, Clone, Default)]
+pub struct PostEffects {
+    pub frame: Vec<PostEffect>,
+    layers: [Vec<PostEffect>; Layer::ALL.len()],
+}
+
+impl PostEffects {
+    pub fn layer(&self, layer: Layer) -> &[PostEffect] {
+        &self.layers[layer as usize]
+    }
+
+    pub fn set_layer(&mut self, layer: Layer, effects: Vec<PostEffect>) {
+        self.layers[layer as usize] = effects;
+    }
+}
+
+/// One full-screen pass. It reads `image`, the output of the previous pass, and `source`,
+/// the input of its effect, both premultiplied. Pixels are read one to one with clamping at
+/// the edges, so the GL programs and `apply` see the same values.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PostPass {
+    /// Gaussian blur along one axis of what is brighter than `threshold`, all of it for 0.
+    Blur { horizontal: bool, radius: f32, threshold: f32 },
+    /// `source` with `image` added `intensity` times.
+    Combine { intensity: f32 },
+    Vignette { radius: f32, intensity: f32 },
+    ColorGrade { brightness: f32, contrast: f32, saturation: f32, tint: [f32; 3] },
+}
+
+impl PostPass {
+    /// Index of the pass program in `PASS_SHADERS`.
+    pub fn program(&self) -> usize {
+        match self {
+            PostPass::Blur { .. } => 0,
+            PostPass::Combine { .. } => 1,
+            PostPass::Vignette { .. } => 2,
+            PostPass::ColorGrade { .. } => 3,
+        }
+    }
+
+    /// Values of `u_params`, two vec4.
+    pub fn params(&self) -> [f32; 8] {
+        match *self {
+            PostPass::Blur { horizontal, radius, threshold } => {
+                let (x, y) = if horizontal { (1.0, 0.0) } else { (0.0, 1.0) };
+                [x, y, radius, threshold, 0.0, 0.0, 0.0, 0.0]
+            }
+            PostPass::Combine { intensity } => [intensity, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+            PostPass::Vignette { radius, intensity } => [radius, intensity, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+            PostPass::ColorGrade { brightness, contrast, saturation, tint } =>
+                [brightness, contrast, saturation, 0.0, tint[0], tint[1], tint[2], 0.0],
+        }
+    }
+
+    /// Software version of the pass program.
+    pub fn apply(&self, image: &RgbaImage, source: &RgbaImage) -> RgbaImage {
+        let mut out = RgbaImage::create(image.width, image.height);
+        for y in 0..image.height {
+            for x in 0..image.width {
+                let color = match *self {
+                    PostPass::Blur { horizontal, radius, threshold } => blur(image, x, y, horizontal, radius, threshold),
+                    PostPass::Combine { intensity } => {
+                        let (c, s) = (read(image, x as i64, y as i64), read(source, x as i64, y as i64));
+                        [0, 1, 2, 3].map(|i| s[i] + c[i] * intensity)
+                    }
+                    PostPass::Vignette { radius, intensity } => {
+                        let u = ((x as f32 + 0.5) / image.width as f32 - 0.5) * 2.0;
+                        let v = ((y as f32 + 0.5) / image.height as f32 - 0.5) * 2.0;
+                        let factor = 1.0 - intensity * smoothstep(radius, CORNER, (u * u + v * v).sqrt());
+                        let c = read(image, x as i64, y as i64);
+                        [c[0] * factor, c[1] * factor, c[2] * factor, c[3]]
+                    }
+                    PostPass::ColorGrade { brightness, contrast, saturation, tint } => {
+                        let c = read(image, x as i64, y as i64);
+                        let a = c[3];
+                        let g = [0, 1, 2].map(|i| if a > 0.0 { c[i] / a * tint[i] } else { 0.0 });
+                        let luma = g[0] * 0.2126 + g[1] * 0.7152 + g[2] * 0.0722;
+                        let g = g.map(|value| {
+                            let value = luma + (value - luma) * saturation;
+                            ((value - 0.5) * contrast + 0.5 + brightness).clamp(0.0, 1.0)
+                        });
+                        [g[0] * a, g[1] * a, g[2] * a, a]
+                    }
+                };
+                out.set_pixel(x, y, color.map(|value| (value * 255.0).round().clamp(0.0, 255.0) as u8));
+            }
+        }
+        out
+    }
+}
+
+/// Runs the passes of `effects` on `image`, the software version of a chain of GL passes.
+pub fn apply_effects(effects: &[PostEffect], image: RgbaImage) -> RgbaImage {
+    let mut image = image;
+    for effect in effects.iter() {
+        let source = image.clone();
+        for pass in effect.passes().iter() {
+            image = pass.apply(&image, &source);
+        }
+    }
+    image
+}
+
+/// Pixel as floats from 0 to 1, coordinates outside the image are clamped to its edges.
+fn read(image: &RgbaImage, x: i64, y: i64) -> [f32; 4] {
+    let x = x.clamp(0, image.width as i64 - 1) as u32;
+    let y = y.clamp(0, image.height as i64 - 1) as u32;
+    image.pixel(x, y).map(|value| value as f32 / 255.0)
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+/// Share of a premultiplied color passed on by a bloom `threshold`, ramping up above it.
+fn bright(c: [f32; 4], threshold: f32) -> f32 {
+    if threshold <= 0.0 {
+        return 1.0;
+    }
+    let luma = c[0] * 0.2126 + c[1] * 0.7152 + c[2] * 0.0722;
+    ((luma - threshold) / (1.0 - threshold).max(0.001)).clamp(0.0, 1.0)
+}
+
+fn blur(image: &RgbaImage, x: u32, y: u32, horizontal: bool, radius: f32, threshold: f32) -> [f32; 4] {
+    let sigma = (radius * 0.5).max(0.5);
+    let mut sum = [0_f32; 4];
+    let mut total = 0.0;
+    let bound = MAX_BLUR_RADIUS as i64;
+    for i in -bound..=bound {
+        let offset = i as f32;
+        if offset.abs() > radius {
+            continue;
+        }
+        let weight = (-offset * offset / (2.0 * sigma * sigma)).exp();
+        let c = if horizontal { read(image, x as i64 + i, y as i64) } else { read(image, x as i64, y as i64 + i) };
+        let weight_bright = weight * bright(c, threshold);
+        for (value, channel) in sum.iter_mut().zip(c.iter()) {
+            *value += channel * weight_bright;
+        }
+        total += weight;
+    }
+    sum.map(|value| value / total)
+}
+
+/// Vertex stage of all passes, a full-screen quad of `a_position` in clip space.
+pub static PASS_VERTEX_SHADER: &str = "attribute vec2 a_position; void main() { gl_Position = vec4(a_position, 0, 1); }";
+
+static PASS_HEADER: &str = "precision mediump float;
+uniform sampler2D u_image; uniform sampler2D u_source; uniform vec2 u_size; uniform vec4 u_params[2];
+vec4 pixel(sampler2D image, vec2 offset) { return texture2D(image, (gl_FragCoord.xy + offset) / u_size); }
+float luma(vec3 c) { return dot(c, vec3(0.2126, 0.7152, 0.0722)); }
+";
+
+/// Fragment stages of the passes, by `PostPass::program`.
+pub fn pass_shaders() -> [String; 4] {
+    [
+        format!("{}
+float bright(vec4 c, float threshold) {{
+  return threshold <= 0.0 ? 1.0 : clamp((luma(c.rgb) - threshold) / max(1.0 - threshold, 0.001), 0.0, 1.0);
+}}
+void main() {{
+  vec2 direction = u_params[0].xy; float radius = u_params[0].z; float threshold = u_params[0].w;
+  float sigma = max(radius * 0.5, 0.5);
+  vec4 sum = vec4(0.0); float total = 0.0;
+  for (int i = -{bound}; i <= {bound}; i++) {{
+    float x = float(i);
+    if (abs(x) > radius) continue;
+    float weight = exp(-x * x / (2.0 * sigma * sigma));
+    vec4 c = pixel(u_image, direction * x);
+    sum += c * weight * bright(c, threshold); total += weight;
+  }}
+  gl_FragColor = sum / total;
+}}", PASS_HEADER, bound = MAX_BLUR_RADIUS as i32),
+        format!("{}
+void main() {{ gl_FragColor = min(pixel(u_source, vec2(0.0)) + pixel(u_image, vec2(0.0)) * u_params[0].x, 1.0); }}", PASS_HEADER),
+        format!("{}
+void main() {{
+  vec2 uv = (gl_FragCoord.xy / u_size - 0.5) * 2.0;
+  float factor = 1.0 - u_params[0].y * smoothstep(u_params[0].x, {corner:.7}, length(uv));
+  vec4 c = pixel(u_image, vec2(0.0));
+  gl_FragColor = vec4(c.rgb * factor, c.a);
+}}", PASS_HEADER, corner = CORNER),
+        format!("{}
+void main() {{
+  vec4 c = pixel(u_image, vec2(0.0));
+  vec3 g = c.a > 0.0 ? c.rgb / c.a * u_params[1].rgb : vec3(0.0);
+  g = mix(vec3(luma(g)), g, u_params[0].z);
+  g = clamp((g - 0.5) * u_params[0].y + 0.5 + u_params[0].x, 0.0, 1.0);
+  gl_FragColor = vec4(g * c.a, c.a);
+}}", PASS_HEADER),
+    ]
+}
@@ -0,0 +1,75 @@
+use js_sys::Date;
+use wasm_bindgen::JsValue;
+use web_sys::Crypto;
+use crate::logger::log_warn;
+
+/// Source of random numbers. Everything that randomizes a scene takes `&mut dyn Rng`,
+/// so it can be driven by a seeded generator in tests and by any source in the browser.
+pub trait Rng {
+    fn next_u32(&mut self) -> u32;
+
+    fn next_u64(&mut self) -> u64 {
+        (self.next_u32() as u64) << 32 | self.next_u32() as u64
+    }
+
+    /// Uniform value in `[min, max)` with 24 bits of precision.
+    fn range(&mut self, min: f32, max: f32) -> f32 {
+        let v = (self.next_u32() >> 8) as f32 / 16_777_216_f32;
+        min + (max - min) * v
+    }
+}
+
+/// Fast seedable xorshift64* generator. Same seed always yields the same sequence.
+pub struct XorShift {
+    state: u64,
+}
+
+impl XorShift {
+    pub fn create(seed: u64) -> XorShift {
+        // xorshift gets stuck on zero state, so mix the seed with a constant first
+        let state = seed ^ 0x9E37_79B9_7F4A_7C15;
+        XorShift { state: if state == 0 { 0x9E37_79B9_7F4A_7C15 } else { state } }
+    }
+
+    /// Seeds a new generator from another source, e.g. `CryptoRng`.
+    pub fn from_rng(source: &mut dyn Rng) -> XorShift {
+        XorShift::create(source.next_u64())
+    }
+}
+
+impl Rng for XorShift {
+    fn next_u32(&mut self) -> u32 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        (self.state.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 32) as u32
+    }
+}
+
+/// Browser `window.crypto()` backed source. Too slow to generate scenes with,
+/// meant to pick seeds for `XorShift`.
+pub struct CryptoRng {
+    crypto: Crypto,
+    /// Seeded from the clock, used if `getRandomValues` fails.
+    fallback: XorShift,
+}
+
+impl CryptoRng {
+    pub fn create() -> Result<CryptoRng, JsValue> {
+        let window = web_sys::window().ok_or("no window")?;
+        Ok(CryptoRng { crypto: window.crypto()?, fallback: XorShift::create(Date::now() as u64) })
+    }
+}
+
+impl Rng for CryptoRng {
+    fn next_u32(&mut self) -> u32 {
+        let mut array = [0_u8; 4];
+        match self.crypto.get_random_values_with_u8_array(&mut array) {
+            Ok(_) => u32::from_le_bytes(array),
+            Err(e) => {
+                log_warn(format!("crypto.getRandomValues failed, {:?}", &e).as_str());
+                self.fallback.next_u32()
+            }
+        }
+    }
+}
@@ -1,43 +1,237 @@
 use wasm_bindgen::JsCast;
 use wasm_bindgen::JsValue;
-use web_sys::{HtmlCanvasElement, CanvasRenderingContext2d, Document, ImageBitmap,
-              WebGlBuffer, WebGlProgram, WebGlRenderingContext, WebGlShader, WebGlTexture};
+use web_sys::{HtmlCanvasElement, CanvasRenderingContext2d, Document, WebGl2RenderingContext, WebGlBuffer,
+              WebGlFramebuffer, WebGlProgram, WebGlRenderingContext, WebGlShader, WebGlTexture, WebGlUniformLocation};
 
-use crate::logger::{log_debug, log_info};
-use crate::geom::Point;
+use crate::background::{background_shaders, Background};
+use crate::buffers::{BufferState, BufferUpdate, Staging};
+use crate::camera::Camera;
+use crate::gl_context::GlContext;
+use crate::layer::{Layer, LayerSet};
+use crate::material::{Material, Materials, MaterialId, Shader, ShaderId, UniformValue, ATTRIBUTE_FLOATS, DEFAULT_MATERIAL};
+use crate::logger::{log_debug, log_error, log_info};
+use crate::postprocess::{pass_shaders, PostEffect, PostEffects, PASS_VERTEX_SHADER};
+use crate::geom::{Mat3, Point, Rect};
+use crate::packer::{pack, AtlasConfig, PackError};
+use crate::texture::{RgbaImage, TextureSource};
+use crate::resource_manager::Registry;
+use crate::scene::Scene;
 
+/// WebGL renderer. With WebGL 2 every sprite is one instance of a quad expanded in the
+/// vertex shader, with WebGL 1 quads are built on the CPU.
 pub struct Renderer {
-    gl: WebGlRenderingContext,
+    gl: GlContext,
+    objects: GlObjects,
+    /// Vertex data of the frame being built.
+    staging: Staging<f32>,
+    /// Index data of the WebGL 1 index buffer being rebuilt.
+    index_staging: Staging<u16>,
+    /// Mirror of the vertex buffer, in bytes.
+    vertices: BufferState,
+    /// Quads covered by the WebGL 1 index buffer.
+    indexed_quads: usize,
+    stats: FrameStats,
+    atlas_config: AtlasConfig,
+    batch_order: BatchOrder,
+    visible_layers: LayerSet,
+    materials: Materials,
+    effects: PostEffects,
+    background: Background,
+}
+
+/// Objects of the current GL context, created again when a lost context is restored.
+struct GlObjects {
+    resources: GlResources,
+    /// Quad vertices with WebGL 1, sprite instances with WebGL 2.
     vertices_buffer: WebGlBuffer,
     indices_buffer: WebGlBuffer,
+    layout: VertexLayout,
+    /// Program of every shader, by shader id.
+    programs: Vec<ShaderProgram>,
+    /// Full-screen quad of post effect passes.
+    pass_buffer: WebGlBuffer,
+    /// Program of every pass, by `PostPass::program`.
+    pass_programs: Vec<PassProgram>,
+    /// Programs of backgrounds drawn like passes, by `Backdrop::program`.
+    background_programs: Vec<PassProgram>,
+    /// Render targets of post effects, created when first needed, see `EFFECT_TARGETS`.
+    targets: Vec<RenderTarget>,
+}
+
+/// Vertex data layout shared by all programs, attribute locations are bound before linking.
+struct VertexLayout {
+    attributes: Vec<Attribute>,
+    /// Bytes per vertex (WebGL 1) or instance (WebGL 2).
+    stride: i32,
+    /// Bytes of vertex data per sprite.
+    sprite_stride: i32,
+}
+
+/// Linked program of a shader with its uniform locations, looked up once after linking.
+struct ShaderProgram {
+    program: WebGlProgram,
+    matrix: Option<WebGlUniformLocation>,
+    texel_size: Option<WebGlUniformLocation>,
+    premultiplied: Option<WebGlUniformLocation>,
+    /// Locations of the declared uniforms, in declaration order.
+    uniforms: Vec<Option<WebGlUniformLocation>>,
+}
+
+/// Linked program of a post effect pass, see `PostPass`.
+struct PassProgram {
     program: WebGlProgram,
+    size: Option<WebGlUniformLocation>,
+    params: Option<WebGlUniformLocation>,
 }
 
+/// Framebuffer drawing into a texture the size of the canvas.
+struct RenderTarget {
+    framebuffer: WebGlFramebuffer,
+    texture: WebGlTexture,
+    width: u32,
+    height: u32,
+}
+
+/// Render targets: the frame when it has effects, the layer being drawn when it has effects,
+/// and two for intermediate passes.
+const FRAME_TARGET: usize = 0;
+const LAYER_TARGET: usize = 1;
+const EFFECT_TARGETS: usize = 4;
+
+struct Attribute {
+    location: u32,
+    size: i32,
+    normalized: bool,
+    offset: i32,
+}
+
+/// GL objects owned by a `Renderer`. Everything registered here is deleted when
+/// the renderer is dropped, textures also when their atlas is deleted.
+struct GlResources {
+    textures: Registry<WebGlTexture>,
+    buffers: Registry<WebGlBuffer>,
+    programs: Registry<WebGlProgram>,
+}
+
+impl GlResources {
+    fn create() -> GlResources {
+        GlResources { textures: Registry::create(), buffers: Registry::create(), programs: Registry::create() }
+    }
+
+    fn delete(&mut self, gl: &GlContext) {
+        for texture in self.textures.drain() {
+            gl.delete_texture(Some(&texture));
+        }
+        for buffer in self.buffers.drain() {
+            gl.delete_buffer(Some(&buffer));
+        }
+        for program in self.programs.drain() {
+            gl.delete_program(Some(&program));
+        }
+    }
+}
+
+/// Work done by a backend for the last frame, to see what rendering costs.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct FrameStats {
+    pub draw_calls: usize,
+    /// Allocations of GPU buffer storage.
+    pub buffer_allocations: usize,
+    /// Bytes of vertex and index data sent to the GPU.
+    pub uploaded_bytes: usize,
+    /// Rebuilds of the WebGL 1 index buffer.
+    pub index_builds: usize,
+    /// Sprites sent to the GPU, or rasterized by the software renderer.
+    pub drawn_sprites: usize,
+    /// Sprites skipped for lying outside the visible part of the world or on a hidden layer.
+    pub culled_sprites: usize,
+    /// Full-screen passes of post effects, see `PostPass`.
+    pub post_passes: usize,
+}
+
+/// Numbers of live resources owned by a render backend.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ResourceCounts {
+    pub textures: usize,
+    pub buffers: usize,
+    pub programs: usize,
+}
+
+/// Maps world coordinates to clip space: the camera view followed by pixels to clip space.
 pub struct Projection {
     pub canvas_width: u32,
     pub canvas_height: u32,
-    matrix: [f32; 9],
+    view: Mat3,
+    matrix: Mat3,
 }
 
 pub struct TexAtlasItem {
-    x: u32,
-    y: u32,
-    width: u32,
-    height: u32,
+    pub page: usize,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// One texture of an atlas. `texture` is assigned by the backend which uploaded the page.
+pub struct AtlasPage {
+    pub width: u32,
+    pub height: u32,
+    pub texture: usize,
 }
 
 pub struct TextureAtlas {
     items: Vec<TexAtlasItem>,
-    width: u32,
-    height: u32,
+    pages: Vec<AtlasPage>,
 }
 
 impl TextureAtlas {
     pub fn empty() -> TextureAtlas {
-        TextureAtlas { items: Vec::new(), width: 0, height: 0 }
+        TextureAtlas { items: Vec::new(), pages: Vec::new() }
+    }
+
+    /// Packs items of the given sizes, spilling into as many pages as `config` allows.
+    pub fn pack(sizes: &[(u32, u32)], config: &AtlasConfig) -> Result<TextureAtlas, PackError> {
+        let packing = pack(sizes, config)?;
+        let items: Vec<TexAtlasItem> = packing.items.iter()
+            .map(|item| TexAtlasItem { page: item.page, x: item.x, y: item.y, width: item.width, height: item.height })
+            .collect();
+        for t in items.iter() {
+            log_info(format!("Texture: page {} {} {} {}x{}", &t.page, &t.x, &t.y, &t.width, &t.height).as_str());
+        }
+        let pages = packing.pages.iter().map(|&(width, height)| AtlasPage { width, height, texture: 0 }).collect();
+        Ok(TextureAtlas { items, pages })
+    }
+
+    /// Number of textures.
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn item(&self, index: usize) -> &TexAtlasItem {
+        &self.items[index]
+    }
+
+    pub fn pages(&self) -> &[AtlasPage] {
+        &self.pages
+    }
+
+    pub fn set_page_texture(&mut self, page: usize, texture: usize) {
+        self.pages[page].texture = texture;
+    }
+
+    /// Page and region of a sprite texture.
+    pub fn region(&self, texture: usize) -> (&AtlasPage, &TexAtlasItem) {
+        let item = &self.items[texture];
+        (&self.pages[item.page], item)
     }
 }
 
+#[derive(Debug, Clone)]
 pub struct Sprite {
     pub texture: usize,
     pub position: Point,
@@ -46,38 +240,550 @@ pub struct Sprite {
     pub width: f32,
     pub height: f32,
     pub alpha: f32,
+    /// Multiplies the quad around the pivot, independently of `width` and `height`.
+    pub scale: Point,
+    /// Mirror the texture horizontally or vertically.
+    pub flip_x: bool,
+    pub flip_y: bool,
+    /// RGBA the shaded color is multiplied by, then `tint_add` is added, before `alpha` applies.
+    pub tint: [f32; 4],
+    pub tint_add: [f32; 4],
+    /// Values of the attributes the material's shader declares, see `Shader::attributes`.
+    pub attributes: [f32; ATTRIBUTE_FLOATS],
+    /// Applied to the quad after position and rotation, e.g. the world transform of a scene node.
+    pub transform: Mat3,
+    pub layer: Layer,
+    /// Order within the layer, lower first. Sprites with equal `z` keep their order.
+    pub z: i32,
+    /// Material of the backend drawing the sprite, see `RenderBackend::add_material`.
+    pub material: MaterialId,
+    pub blend: BlendMode,
+}
+
+impl Default for Sprite {
+    fn default() -> Sprite {
+        Sprite {
+            texture: 0,
+            position: Point { x: 0.0, y: 0.0 },
+            pivot: Point { x: 0.0, y: 0.0 },
+            rotation: 0.0,
+            width: 0.0,
+            height: 0.0,
+            alpha: 1.0,
+            scale: Point { x: 1.0, y: 1.0 },
+            flip_x: false,
+            flip_y: false,
+            tint: [1.0; 4],
+            tint_add: [0.0; 4],
+            attributes: [0.0; ATTRIBUTE_FLOATS],
+            transform: Mat3::identity(),
+            layer: Layer::Flakes,
+            z: 0,
+            material: DEFAULT_MATERIAL,
+            blend: BlendMode::Normal,
+        }
+    }
+}
+
+/// Corner of a sprite quad: position in pixels, texture coordinates, alpha, and the tint and
+/// attributes, which are the same for all corners.
+#[derive(Debug, Clone, Copy)]
+pub struct Vertex {
+    pub position: Point,
+    pub tex_coord: Point,
+    pub alpha: f32,
+    pub tint: [f32; 4],
+    pub tint_add: [f32; 4],
+    pub attributes: [f32; ATTRIBUTE_FLOATS],
+}
+
+/// How a sprite is combined with what is drawn below it. Atlas textures are stored with
+/// premultiplied alpha, so filtering never mixes in the color of transparent pixels, and
+/// every mode blends the premultiplied output of the fragment shader.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum BlendMode {
+    /// Source over, shaders work with straight colors.
+    Normal,
+    /// Source over, shaders work with premultiplied colors, e.g. to emit light where alpha is 0.
+    Premultiplied,
+    /// Adds the sprite's color, e.g. for glows.
+    Additive,
+    /// Multiplies the colors below by the sprite's color, darkening them.
+    Multiply,
+    /// Inverse of multiplying the inverted colors, lightening them.
+    Screen,
+}
+
+impl BlendMode {
+    /// Whether shaders get and return premultiplied colors.
+    pub fn premultiplied(self) -> bool {
+        self == BlendMode::Premultiplied
+    }
+
+    /// Source and destination factors of `blend_func`.
+    fn factors(self) -> (u32, u32) {
+        match self {
+            BlendMode::Normal | BlendMode::Premultiplied =>
+                (WebGlRenderingContext::ONE, WebGlRenderingContext::ONE_MINUS_SRC_ALPHA),
+            BlendMode::Additive => (WebGlRenderingContext::ONE, WebGlRenderingContext::ONE),
+            BlendMode::Multiply => (WebGlRenderingContext::DST_COLOR, WebGlRenderingContext::ONE_MINUS_SRC_ALPHA),
+            BlendMode::Screen => (WebGlRenderingContext::ONE, WebGlRenderingContext::ONE_MINUS_SRC_COLOR),
+        }
+    }
+}
+
+/// How sprites on different atlas pages are split into draw calls.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BatchOrder {
+    /// Sprites are painted exactly in the given order, a new batch starts whenever the page,
+    /// the material or the blend mode changes.
+    Preserve,
+    /// Sprites are grouped by material, blend mode and page, one draw call per group. Order is
+    /// kept within a group only, layers are still drawn one after another.
+    ByPage,
+}
+
+/// Most sprites drawn by one call: quad vertices of a batch are numbered from 0,
+/// and 4 vertices per sprite have to fit into `u16` indices.
+pub const MAX_BATCH_SPRITES: usize = 16384;
+
+/// Range of `count` sprites starting at `start` of the draw order, all on one layer and
+/// atlas page and with one material and blend mode. `count` is at most `MAX_BATCH_SPRITES`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Batch {
+    pub layer: Layer,
+    pub page: usize,
+    pub material: MaterialId,
+    pub blend: BlendMode,
+    pub start: usize,
+    pub count: usize,
+}
+
+/// Everything able to draw sprites: the WebGL `Renderer` and the software `CpuRenderer`.
+pub trait RenderBackend {
+    /// Packs the sources into an atlas and uploads its pages.
+    fn create_atlas(&mut self, sources: &[TextureSource]) -> Result<TextureAtlas, JsValue>;
+    /// Padding, extrusion and size limits used by the atlas builders.
+    fn set_atlas_config(&mut self, config: AtlasConfig);
+    fn set_batch_order(&mut self, order: BatchOrder);
+    /// Shows or hides all sprites of `layer`, every layer is visible initially.
+    fn set_layer_visible(&mut self, layer: Layer, visible: bool);
+    /// Compiles a shader for materials to use.
+    fn add_shader(&mut self, shader: Shader) -> Result<ShaderId, JsValue>;
+    /// New material of `shader` with the default uniform values.
+    fn add_material(&mut self, shader: ShaderId) -> Result<MaterialId, JsValue>;
+    fn set_uniform(&mut self, material: MaterialId, name: &str, value: UniformValue) -> Result<(), JsValue>;
+    fn materials(&self) -> &Materials;
+    /// Full-screen effects applied in order to the finished frame, none initially.
+    fn set_post_effects(&mut self, effects: Vec<PostEffect>);
+    /// Effects applied in order to everything drawn on `layer` before it is composed with the
+    /// layers below, e.g. a blur of distant sprites.
+    fn set_layer_effects(&mut self, layer: Layer, effects: Vec<PostEffect>);
+    /// What is drawn behind all sprites, opaque black initially.
+    fn set_background(&mut self, background: Background);
+    /// Deletes the textures of an atlas created by this backend.
+    fn delete_atlas(&mut self, atlas: TextureAtlas);
+    fn resources(&self) -> ResourceCounts;
+    fn frame_stats(&self) -> FrameStats;
+    /// Resizes the canvas backing store and the viewport, keeping textures.
+    fn resize(&mut self, width: u32, height: u32);
+    /// Recreates GPU objects after the context was lost and restored. Textures of all
+    /// atlases are gone by then, the atlases have to be created again. Called again after
+    /// a failure, it first deletes what the failed attempt created.
+    fn restore(&mut self) -> Result<(), JsValue>;
+    fn render(&mut self, projection: &Projection, sprites: &[Sprite], atlas: &TextureAtlas);
+
+    /// Draws the visible sprites of a scene graph, see `Scene::flatten`.
+    fn render_scene(&mut self, projection: &Projection, scene: &Scene, atlas: &TextureAtlas) {
+        self.render(projection, &scene.flatten(), atlas);
+    }
+}
+
+impl Sprite {
+    /// Corners in pixels, clockwise starting from the top left one, scaled and rotated around
+    /// the pivot and transformed by `transform`.
+    pub fn corners(&self) -> [Point; 4] {
+        let (sin, cos) = self.rotation.sin_cos();
+        let pivot = Point { x: self.pivot.x * self.scale.x, y: self.pivot.y * self.scale.y };
+        let p = self.position - pivot.rotate_sin_cos(sin, cos);
+        let width_rotated = Point { x: self.width * self.scale.x, y: 0.0 }.rotate_sin_cos(sin, cos);
+        let height_rotated = Point { x: 0.0, y: self.height * self.scale.y }.rotate_sin_cos(sin, cos);
+        let t = &self.transform;
+        [
+            t.transform_point(p),
+            t.transform_point(p + width_rotated),
+            t.transform_point(p + width_rotated + height_rotated),
+            t.transform_point(p + height_rotated),
+        ]
+    }
+
+    /// Axis aligned box around the rotated and transformed quad.
+    pub fn bounds(&self) -> Rect {
+        Rect::bounding(&self.corners()).unwrap()
+    }
+
+    /// Texture rectangle on the atlas page as left, top, right and bottom texture coordinates,
+    /// swapped for flipped sprites.
+    pub fn tex_rect(&self, atlas: &TextureAtlas) -> [f32; 4] {
+        let (page, tex) = atlas.region(self.texture);
+        let atlas_width = page.width as f32;
+        let atlas_height = page.height as f32;
+        let (mut u0, mut u1) = (tex.x as f32 / atlas_width, (tex.x + tex.width) as f32 / atlas_width);
+        let (mut v0, mut v1) = (tex.y as f32 / atlas_height, (tex.y + tex.height) as f32 / atlas_height);
+        if self.flip_x {
+            std::mem::swap(&mut u0, &mut u1);
+        }
+        if self.flip_y {
+            std::mem::swap(&mut v0, &mut v1);
+        }
+        [u0, v0, u1, v1]
+    }
+
+    /// Quad corners as `corners` with texture coordinates, alpha and tint.
+    /// Both backends draw it as triangles (0, 1, 2) and (0, 2, 3).
+    pub fn quad(&self, atlas: &TextureAtlas) -> [Vertex; 4] {
+        let [u0, v0, u1, v1] = self.tex_rect(atlas);
+        let [c0, c1, c2, c3] = self.corners();
+        let vertex = |position: Point, u: f32, v: f32| Vertex {
+            position,
+            tex_coord: Point { x: u, y: v },
+            alpha: self.alpha,
+            tint: self.tint,
+            tint_add: self.tint_add,
+            attributes: self.attributes,
+        };
+        [vertex(c0, u0, v0), vertex(c1, u1, v0), vertex(c2, u1, v1), vertex(c3, u0, v1)]
+    }
+}
+
+/// Returns sprite indices in drawing order, by layer and `z`, and the batches splitting them
+/// by atlas page and by `MAX_BATCH_SPRITES`.
+pub fn batch_sprites(sprites: &[Sprite], atlas: &TextureAtlas, order: BatchOrder) -> (Vec<usize>, Vec<Batch>) {
+    batch_indices(sprites, (0..sprites.len()).collect(), atlas, order)
+}
+
+/// Same as `batch_sprites` leaving out sprites on layers missing from `layers` and sprites
+/// whose bounds lie completely outside `visible`, e.g. `Projection::visible_rect`.
+pub fn batch_visible_sprites(sprites: &[Sprite], atlas: &TextureAtlas, order: BatchOrder,
+                             visible: &Rect, layers: LayerSet) -> (Vec<usize>, Vec<Batch>) {
+    let indices = (0..sprites.len())
+        .filter(|&i| layers.contains(sprites[i].layer) && sprites[i].bounds().intersects(visible))
+        .collect();
+    batch_indices(sprites, indices, atlas, order)
+}
+
+fn batch_indices(sprites: &[Sprite], mut draw_order: Vec<usize>, atlas: &TextureAtlas,
+                 order: BatchOrder) -> (Vec<usize>, Vec<Batch>) {
+    let page = |i: usize| atlas.items[sprites[i].texture].page;
+    draw_order.sort_by_key(|&i| (sprites[i].layer, sprites[i].z));
+    if order == BatchOrder::ByPage {
+        // switching programs costs more than switching textures
+        draw_order.sort_by_key(|&i| (sprites[i].layer, sprites[i].material, sprites[i].blend, page(i)));
+    }
+    let mut batches: Vec<Batch> = Vec::new();
+    for (position, &i) in draw_order.iter().enumerate() {
+        let (layer, page, material, blend) = (sprites[i].layer, page(i), sprites[i].material, sprites[i].blend);
+        match batches.last_mut() {
+            Some(batch) if batch.layer == layer && batch.page == page && batch.material == material
+                && batch.blend == blend && batch.count < MAX_BATCH_SPRITES => batch.count += 1,
+            _ => batches.push(Batch { layer, page, material, blend, start: position, count: 1 })
+        }
+    }
+    (draw_order, batches)
+}
+
+/// Indices of triangles (0, 1, 2) and (0, 2, 3) of `count` quads, vertices numbered from 0.
+/// Every batch is drawn with them, its vertex attributes start at its first quad.
+pub fn quad_indices(count: usize) -> Vec<u16> {
+    assert!(count <= MAX_BATCH_SPRITES, "{} quads do not fit into u16 indices", count);
+    let mut indices = Vec::with_capacity(count * 6);
+    for i in 0..count {
+        let n = (i * 4) as u16;
+        indices.extend_from_slice(&[n, n + 1, n + 2, n, n + 2, n + 3]);
+    }
+    indices
+}
+
+/// Fragment program of `shader` between the `header` and `main` of a WebGL version.
+pub fn fragment_source(shader: &Shader, header: &str, main: &str) -> String {
+    let uniforms: String = shader.uniforms.iter()
+        .map(|(name, value)| format!("uniform {} {};\n", value.glsl_type(), name))
+        .collect();
+    format!("{}{}{}{}\n{}", header, uniforms, shader.attribute_defines("v_attributes"), shader.glsl, main)
+}
+
+/// Stages four vertices per sprite of `draw_order` for WebGL 1, laid out as `QUAD_ATTRIBUTES`.
+pub fn stage_quads(staging: &mut Staging<f32>, sprites: &[Sprite], draw_order: &[usize], atlas: &TextureAtlas) {
+    staging.clear();
+    for &sprite_index in draw_order.iter() {
+        for v in sprites[sprite_index].quad(atlas).iter() {
+            staging.push(&[v.position.x, v.position.y, v.tex_coord.x, v.tex_coord.y, v.alpha]);
+            staging.push(&v.tint);
+            staging.push(&v.tint_add);
+            staging.push(&v.attributes);
+        }
+    }
+}
+
+/// Stages one instance per sprite of `draw_order` for WebGL 2, laid out as `INSTANCE_ATTRIBUTES`.
+pub fn stage_instances(staging: &mut Staging<f32>, sprites: &[Sprite], draw_order: &[usize], atlas: &TextureAtlas) {
+    staging.clear();
+    for &sprite_index in draw_order.iter() {
+        let sprite = &sprites[sprite_index];
+        staging.push(&[
+            sprite.position.x, sprite.position.y, sprite.pivot.x, sprite.pivot.y,
+            sprite.width, sprite.height, sprite.rotation, sprite.alpha,
+        ]);
+        staging.push(&sprite.tex_rect(atlas));
+        let m = &sprite.transform.m;
+        staging.push(&[m[0], m[3], m[6], m[1], m[4], m[7]]);
+        staging.push(&[sprite.scale.x, sprite.scale.y]);
+        staging.push(&sprite.tint);
+        staging.push(&sprite.tint_add);
+        staging.push(&sprite.attributes);
+    }
 }
 
 impl Projection {
     pub fn create(canvas_width: u32, canvas_height: u32) -> Projection {
-        let matrix: [f32; 9] = [
-            2_f32 / canvas_width as f32, 0_f32, 0_f32,
-            0_f32, -2_f32 / canvas_height as f32, 0_f32,
-            -1_f32, 1_f32, 1_f32
-        ];
-        Projection { canvas_width, canvas_height, matrix }
+        let mut projection = Projection { canvas_width, canvas_height, view: Mat3::identity(), matrix: Mat3::identity() };
+        projection.update_matrix();
+        projection
+    }
+
+    /// Changes the canvas size keeping the camera view.
+    pub fn resize(&mut self, canvas_width: u32, canvas_height: u32) {
+        self.canvas_width = canvas_width;
+        self.canvas_height = canvas_height;
+        self.update_matrix();
+    }
+
+    /// Views the world through `camera`, call again whenever the camera changes.
+    pub fn set_camera(&mut self, camera: &Camera) {
+        self.view = camera.view_matrix();
+        self.update_matrix();
+    }
+
+    fn update_matrix(&mut self) {
+        let clip = Mat3::translation(Point { x: -1.0, y: 1.0 })
+            * Mat3::scale(Point { x: 2.0 / self.canvas_width as f32, y: -2.0 / self.canvas_height as f32 });
+        self.matrix = clip * self.view;
+    }
+
+    /// Maps a point in world coordinates to clip space.
+    pub fn to_clip(&self, p: Point) -> Point {
+        self.matrix.transform_point(p)
+    }
+
+    /// Part of the world shown on the canvas, an axis aligned box around it when the camera is rotated.
+    /// A singular matrix, e.g. of a camera with zero zoom, squashes the world to a line or a point
+    /// that covers no pixels, so the rect is then empty and culls every sprite.
+    pub fn visible_rect(&self) -> Rect {
+        let inverse = match self.matrix.invert() {
+            Some(inverse) => inverse,
+            None => return Rect { x: 0.0, y: 0.0, width: 0.0, height: 0.0 }
+        };
+        let corners = [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)]
+            .map(|(x, y)| inverse.transform_point(Point { x, y }));
+        Rect::bounding(&corners).unwrap()
+    }
+
+    /// World to clip space transform, as uploaded to the shaders.
+    pub fn matrix(&self) -> &Mat3 {
+        &self.matrix
     }
 }
 
 impl Renderer {
     pub fn init(canvas: &HtmlCanvasElement) -> Result<Renderer, JsValue> {
-        let context = canvas.get_context("webgl")?.unwrap();
-        let gl: WebGlRenderingContext = context.dyn_into::<WebGlRenderingContext>()?;
+        let gl = match canvas.get_context("webgl2")? {
+            Some(context) => GlContext::WebGl2(context.dyn_into::<WebGl2RenderingContext>()?),
+            None => {
+                let context = canvas.get_context("webgl")?.ok_or("WebGL is not supported")?;
+                GlContext::WebGl1(context.dyn_into::<WebGlRenderingContext>()?)
+            }
+        };
+        let materials = Materials::create();
+        let objects = Renderer::create_objects(&gl, &materials)?;
+        let max_texture_size = gl.get_parameter(WebGlRenderingContext::MAX_TEXTURE_SIZE)?
+            .as_f64().unwrap_or(2048_f64) as u32;
+        let version = match gl {
+            GlContext::WebGl1(_) => "WebGL 1",
+            GlContext::WebGl2(_) => "WebGL 2, instanced",
+        };
+        log_info(format!("Renderer initialized, {}, max texture size {}", version, max_texture_size).as_str());
+        Ok(Renderer {
+            gl,
+            objects,
+            staging: Staging::create(),
+            index_staging: Staging::create(),
+            vertices: BufferState::create(),
+            indexed_quads: 0,
+            stats: FrameStats::default(),
+            atlas_config: AtlasConfig::create(max_texture_size),
+            batch_order: BatchOrder::Preserve,
+            visible_layers: LayerSet::all(),
+            materials,
+            effects: PostEffects::default(),
+            background: Background::default(),
+        })
+    }
+
+    /// Creates buffers and the programs of all shaders and sets up the GL state they are used with.
+    /// Creates the objects of a context, deleting the ones created already if any fails.
+    fn create_objects(gl: &GlContext, materials: &Materials) -> Result<GlObjects, JsValue> {
+        let mut resources = GlResources::create();
+        match Renderer::create_tracked_objects(gl, materials, &mut resources) {
+            Ok(mut objects) => {
+                objects.resources = resources;
+                Ok(objects)
+            }
+            Err(e) => {
+                resources.delete(gl);
+                Err(e)
+            }
+        }
+    }
+
+    /// Objects of `create_objects`, every one registered in `resources` as soon as it exists.
+    fn create_tracked_objects(gl: &GlContext, materials: &Materials, resources: &mut GlResources) -> Result<GlObjects, JsValue> {
         let vertices_buffer = gl.create_buffer().ok_or("failed to create vertices buffer")?;
+        resources.buffers.insert(vertices_buffer.clone());
         let indices_buffer = gl.create_buffer().ok_or("failed to create indices buffer")?;
-        let vert_shader = Renderer::compile_shader(&gl, WebGlRenderingContext::VERTEX_SHADER, VERTEX_SHADER)?;
-        let frag_shader = Renderer::compile_shader(&gl, WebGlRenderingContext::FRAGMENT_SHADER, FRAGMENT_SHADER)?;
-        let program = Renderer::link_program(&gl, &vert_shader, &frag_shader)?;
-        gl.use_program(Some(&program));
+        resources.buffers.insert(indices_buffer.clone());
+        let mut programs = Vec::with_capacity(materials.shaders().len());
+        for shader in materials.shaders().iter() {
+            let program = Renderer::create_program(gl, shader)?;
+            resources.programs.insert(program.program.clone());
+            programs.push(program);
+        }
+        let pass_buffer = gl.create_buffer().ok_or("failed to create pass buffer")?;
+        resources.buffers.insert(pass_buffer.clone());
+        gl.bind_buffer(WebGlRenderingContext::ARRAY_BUFFER, Some(&pass_buffer));
+        let mut quad = Staging::create();
+        quad.push(&[-1.0, -1.0, 1.0, -1.0, -1.0, 1.0, 1.0, 1.0]);
+        gl.buffer_data_with_u8_array(WebGlRenderingContext::ARRAY_BUFFER, quad.as_bytes(), WebGlRenderingContext::STATIC_DRAW);
+        let mut pass_programs = Vec::new();
+        for source in pass_shaders().iter() {
+            let program = Renderer::create_pass_program(gl, source)?;
+            resources.programs.insert(program.program.clone());
+            pass_programs.push(program);
+        }
+        let mut background_programs = Vec::new();
+        for source in background_shaders().iter() {
+            let program = Renderer::create_pass_program(gl, source)?;
+            resources.programs.insert(program.program.clone());
+            background_programs.push(program);
+        }
         gl.disable(WebGlRenderingContext::STENCIL_TEST);
         gl.disable(WebGlRenderingContext::DEPTH_TEST);
         gl.enable(WebGlRenderingContext::BLEND);
-        gl.blend_func(WebGlRenderingContext::SRC_ALPHA, WebGlRenderingContext::ONE_MINUS_SRC_ALPHA);
-        log_info("Renderer initialized");
-        Ok(Renderer { gl, vertices_buffer, indices_buffer, program })
+        // atlas pages are premultiplied on upload, see `BlendMode`
+        gl.pixel_storei(WebGlRenderingContext::UNPACK_PREMULTIPLY_ALPHA_WEBGL, 1);
+        if let GlContext::WebGl2(_) = gl {
+            // every instance is the same quad, its corners are taken from gl_VertexID
+            gl.bind_buffer(WebGlRenderingContext::ELEMENT_ARRAY_BUFFER, Some(&indices_buffer));
+            let mut indices = Staging::create();
+            indices.push(&[0, 1, 2, 0, 2, 3]);
+            Renderer::upload_indices(gl, &indices);
+        }
+        let layout = Renderer::vertex_layout(gl);
+        Ok(GlObjects {
+            resources: GlResources::create(), vertices_buffer, indices_buffer, layout, programs, pass_buffer, pass_programs, background_programs,
+            targets: Vec::new(),
+        })
     }
 
-    fn compile_shader(gl: &WebGlRenderingContext, shader_type: u32, source: &str) -> Result<WebGlShader, String> {
+    fn attribute_specs(gl: &GlContext) -> &'static [AttributeSpec] {
+        match gl {
+            GlContext::WebGl1(_) => &QUAD_ATTRIBUTES[..],
+            GlContext::WebGl2(_) => &INSTANCE_ATTRIBUTES[..],
+        }
+    }
+
+    /// Enables the attributes, which stay enabled for the context's lifetime. Every attribute
+    /// is at the location of its index, see `create_program`.
+    fn vertex_layout(gl: &GlContext) -> VertexLayout {
+        let (stride, sprite_stride) = match gl {
+            GlContext::WebGl1(_) => (QUAD_STRIDE as i32, QUAD_STRIDE as i32 * 4),
+            GlContext::WebGl2(_) => (INSTANCE_STRIDE as i32, INSTANCE_STRIDE as i32),
+        };
+        let attributes: Vec<Attribute> = Renderer::attribute_specs(gl).iter().enumerate()
+            .map(|(location, &(_, size, normalized, offset))| Attribute { location: location as u32, size, normalized, offset })
+            .collect();
+        for attribute in attributes.iter() {
+            gl.enable_vertex_attrib_array(attribute.location);
+            if let GlContext::WebGl2(gl) = gl {
+                gl.vertex_attrib_divisor(attribute.location, 1);
+            }
+        }
+        VertexLayout { attributes, stride, sprite_stride }
+    }
+
+    /// Compiles the shader into a program with the backend's vertex stage.
+    fn create_program(gl: &GlContext, shader: &Shader) -> Result<ShaderProgram, JsValue> {
+        let (vert_source, header, main) = match gl {
+            GlContext::WebGl1(_) => (VERTEX_SHADER, FRAGMENT_HEADER, FRAGMENT_MAIN),
+            GlContext::WebGl2(_) => (INSTANCED_VERTEX_SHADER, INSTANCED_FRAGMENT_HEADER, INSTANCED_FRAGMENT_MAIN),
+        };
+        let frag_source = fragment_source(shader, header, main);
+        let vert_shader = Renderer::compile_shader(gl, WebGlRenderingContext::VERTEX_SHADER, vert_source)?;
+        let frag_shader = Renderer::compile_shader(gl, WebGlRenderingContext::FRAGMENT_SHADER, &frag_source)
+            .map_err(|e| format!("Shader {}: {}", shader.name, e));
+        let frag_shader = match frag_shader {
+            Ok(frag_shader) => frag_shader,
+            Err(e) => {
+                gl.delete_shader(Some(&vert_shader));
+                return Err(JsValue::from(e));
+            }
+        };
+        let program = gl.create_program().ok_or("Unable to create program object")?;
+        for (location, (name, _, _, _)) in Renderer::attribute_specs(gl).iter().enumerate() {
+            gl.bind_attrib_location(&program, location as u32, name);
+        }
+        let linked = Renderer::link_program(gl, program, &vert_shader, &frag_shader);
+        // the program keeps what it needs, shaders are not used after linking
+        gl.delete_shader(Some(&vert_shader));
+        gl.delete_shader(Some(&frag_shader));
+        let program = linked.map_err(|e| format!("Shader {}: {}", shader.name, e))?;
+        let uniforms = shader.uniforms.iter().map(|(name, _)| gl.get_uniform_location(&program, name)).collect();
+        Ok(ShaderProgram {
+            matrix: gl.get_uniform_location(&program, "u_matrix"),
+            texel_size: gl.get_uniform_location(&program, "u_texelSize"),
+            premultiplied: gl.get_uniform_location(&program, "u_premultiplied"),
+            uniforms,
+            program,
+        })
+    }
+
+    /// Compiles a post effect pass, `a_position` is at location 0 like the first sprite attribute.
+    fn create_pass_program(gl: &GlContext, source: &str) -> Result<PassProgram, JsValue> {
+        let vert_shader = Renderer::compile_shader(gl, WebGlRenderingContext::VERTEX_SHADER, PASS_VERTEX_SHADER)?;
+        let frag_shader = match Renderer::compile_shader(gl, WebGlRenderingContext::FRAGMENT_SHADER, source) {
+            Ok(frag_shader) => frag_shader,
+            Err(e) => {
+                gl.delete_shader(Some(&vert_shader));
+                return Err(JsValue::from(format!("Post effect pass: {}", e)));
+            }
+        };
+        let program = gl.create_program().ok_or("Unable to create program object")?;
+        gl.bind_attrib_location(&program, 0, "a_position");
+        let linked = Renderer::link_program(gl, program, &vert_shader, &frag_shader);
+        gl.delete_shader(Some(&vert_shader));
+        gl.delete_shader(Some(&frag_shader));
+        let program = linked.map_err(|e| format!("Post effect pass: {}", e))?;
+        // the pass output is sampled on unit 0, the effect input on unit 1
+        gl.use_program(Some(&program));
+        gl.uniform1i(gl.get_uniform_location(&program, "u_image").as_ref(), 0);
+        gl.uniform1i(gl.get_uniform_location(&program, "u_source").as_ref(), 1);
+        Ok(PassProgram {
+            size: gl.get_uniform_location(&program, "u_size"),
+            params: gl.get_uniform_location(&program, "u_params"),
+            program,
+        })
+    }
+
+    fn compile_shader(gl: &GlContext, shader_type: u32, source: &str) -> Result<WebGlShader, String> {
         let shader = gl.create_shader(shader_type)
             .ok_or_else(|| String::from("Unable to create shader object"))?;
         gl.shader_source(&shader, source);
@@ -92,9 +798,8 @@ impl Renderer {
         }
     }
 
-    fn link_program(gl: &WebGlRenderingContext, vert_shader: &WebGlShader, frag_shader: &WebGlShader) -> Result<WebGlProgram, String> {
-        let program = gl.create_program()
-            .ok_or_else(|| String::from("Unable to create shader object"))?;
+    fn link_program(gl: &GlContext, program: WebGlProgram, vert_shader: &WebGlShader,
+                    frag_shader: &WebGlShader) -> Result<WebGlProgram, String> {
         gl.attach_shader(&program, vert_shader);
         gl.attach_shader(&program, frag_shader);
         gl.link_program(&program);
@@ -103,177 +808,589 @@ impl Renderer {
             .as_bool().unwrap_or(false) {
             Ok(program)
         } else {
-            Err(gl.get_program_info_log(&program)
-                .unwrap_or_else(|| String::from("Unknown error creating program object")))
+            let log = gl.get_program_info_log(&program)
+                .unwrap_or_else(|| String::from("Unknown error creating program object"));
+            gl.delete_program(Some(&program));
+            Err(log)
+        }
+    }
+
+    /// Makes the program of `material` current and sets its uniforms.
+    fn use_material(&self, material: &Material, projection: &Projection) {
+        let program = &self.objects.programs[material.shader];
+        self.gl.use_program(Some(&program.program));
+        self.gl.uniform_matrix3fv_with_f32_array(program.matrix.as_ref(), false, &projection.matrix.m);
+        for (location, value) in program.uniforms.iter().zip(material.values.iter()) {
+            match *value {
+                UniformValue::Float(x) => self.gl.uniform1f(location.as_ref(), x),
+                UniformValue::Vec2(v) => self.gl.uniform2f(location.as_ref(), v.x, v.y),
+                UniformValue::Vec4([x, y, z, w]) => self.gl.uniform4f(location.as_ref(), x, y, z, w),
+            }
         }
     }
 
-    fn update_buffers(&self, sprites: &Vec<Sprite>, atlas: &TextureAtlas) {
+    /// Builds quads for WebGL 1 drawing.
+    fn update_buffers(&mut self, sprites: &[Sprite], draw_order: &[usize], atlas: &TextureAtlas) {
         log_debug("Renderer: update buffers");
-        let mut vertices: Vec<f32> = Vec::with_capacity(sprites.len() * 20);
-        let mut indices: Vec<u16> = Vec::with_capacity(sprites.len() * 6);
-        let atlas_width = atlas.width as f32;
-        let atlas_height = atlas.height as f32;
-        for (i, sprite) in sprites.iter().enumerate() {
-            let tex = &atlas.items[sprite.texture];
-            let p = sprite.position - sprite.pivot.rotate(sprite.rotation);
-            let width_rotated = Point { x: sprite.width, y: 0.0 }.rotate(sprite.rotation);
-            let height_rotated = Point { x: 0.0, y: sprite.height }.rotate(sprite.rotation);
-            vertices.extend_from_slice(&[
-                p.x, p.y, tex.x as f32 / atlas_width, tex.y as f32 / atlas_height, sprite.alpha,
-                p.x + width_rotated.x, p.y + width_rotated.y, (tex.x + tex.width) as f32 / atlas_width, tex.y as f32 / atlas_height, sprite.alpha,
-                p.x + width_rotated.x + height_rotated.x, p.y + width_rotated.y + height_rotated.y, (tex.x + tex.width) as f32 / atlas_width, (tex.y + tex.height) as f32 / atlas_height, sprite.alpha,
-                p.x + height_rotated.x, p.y + height_rotated.y, tex.x as f32 / atlas_width, (tex.y + tex.height) as f32 / atlas_height, sprite.alpha
-            ]);
-            let n = i as u16 * 4;
-            indices.extend_from_slice(&[n, n + 1, n + 2, n, n + 2, n + 3]);
-        }
-
-        self.gl.bind_buffer(WebGlRenderingContext::ARRAY_BUFFER, Some(&self.vertices_buffer));
-        // Note that `Float32Array::view` is somewhat dangerous (hence the
-        // `unsafe`!). This is creating a raw view into our module's
-        // `WebAssembly.Memory` buffer, but if we allocate more pages for ourself
-        // (aka do a memory allocation in Rust) it'll cause the buffer to change,
-        // causing the `Float32Array` to be invalid.
-        //
-        // As a result, after `Float32Array::view` we have to be very careful not to
-        // do any memory allocations before it's dropped.
-        unsafe {
-            let vert_array = js_sys::Float32Array::view(&vertices.as_slice());
-            self.gl.buffer_data_with_array_buffer_view(WebGlRenderingContext::ARRAY_BUFFER, &vert_array, WebGlRenderingContext::DYNAMIC_DRAW);
-        }
-        let location = self.gl.get_attrib_location(&self.program, "a_position") as u32;
-        self.gl.enable_vertex_attrib_array(location);
-        self.gl.vertex_attrib_pointer_with_i32(location, 2, WebGlRenderingContext::FLOAT, false, 20, 0);
-        let location = self.gl.get_attrib_location(&self.program, "a_texCoord") as u32;
-        self.gl.enable_vertex_attrib_array(location);
-        self.gl.vertex_attrib_pointer_with_i32(location, 2, WebGlRenderingContext::FLOAT, true, 20, 8);
-        let location = self.gl.get_attrib_location(&self.program, "a_alpha") as u32;
-        self.gl.enable_vertex_attrib_array(location);
-        self.gl.vertex_attrib_pointer_with_i32(location, 1, WebGlRenderingContext::FLOAT, true, 20, 16);
-
-
-        self.gl.bind_buffer(WebGlRenderingContext::ELEMENT_ARRAY_BUFFER, Some(&self.indices_buffer));
-        unsafe {
-            let ind_array = js_sys::Uint16Array::view(&indices);
-            self.gl.buffer_data_with_array_buffer_view(WebGlRenderingContext::ELEMENT_ARRAY_BUFFER, &ind_array, WebGlRenderingContext::STATIC_DRAW);
-        }
-    }
-
-    pub fn create_texture_with_images(&self, document: &Document, images: &Vec<ImageBitmap>) -> Result<TextureAtlas, JsValue> {
-        let mut textures: Vec<TexAtlasItem> = Vec::with_capacity(images.len());
-        let mut total_height = 0_u32;
-        let mut total_width = 0_u32;
-        for image in images.iter() {
-            let height = image.height() as u32;
-            let width = image.width() as u32;
-            if total_height < height {
-                total_height = height;
+        stage_quads(&mut self.staging, sprites, draw_order, atlas);
+        self.upload_vertices();
+
+        // indices do not depend on sprites, they only have to cover the largest batch
+        let quads = draw_order.len().min(MAX_BATCH_SPRITES);
+        if quads > self.indexed_quads {
+            self.indexed_quads = quads.max(self.indexed_quads * 2).min(MAX_BATCH_SPRITES);
+            self.index_staging.clear();
+            self.index_staging.push(&quad_indices(self.indexed_quads));
+            self.gl.bind_buffer(WebGlRenderingContext::ELEMENT_ARRAY_BUFFER, Some(&self.objects.indices_buffer));
+            Renderer::upload_indices(&self.gl, &self.index_staging);
+            self.stats.index_builds += 1;
+            self.stats.buffer_allocations += 1;
+            self.stats.uploaded_bytes += self.index_staging.as_bytes().len();
+        }
+    }
+
+    /// Uploads one instance per sprite for WebGL 2 drawing, see `INSTANCED_VERTEX_SHADER`.
+    fn update_instances(&mut self, sprites: &[Sprite], draw_order: &[usize], atlas: &TextureAtlas) {
+        log_debug("Renderer: update instances");
+        stage_instances(&mut self.staging, sprites, draw_order, atlas);
+        self.upload_vertices();
+    }
+
+    /// Brings the vertex buffer up to date with the staged data, reallocating its storage
+    /// only when it is too small.
+    fn upload_vertices(&mut self) {
+        self.gl.bind_buffer(WebGlRenderingContext::ARRAY_BUFFER, Some(&self.objects.vertices_buffer));
+        let update = self.vertices.update(&mut self.staging);
+        let data = self.staging.as_bytes();
+        let range = match update {
+            BufferUpdate::None => return,
+            BufferUpdate::Reallocate { capacity } => {
+                self.gl.buffer_data_with_i32(WebGlRenderingContext::ARRAY_BUFFER, capacity as i32,
+                                             WebGlRenderingContext::DYNAMIC_DRAW);
+                self.stats.buffer_allocations += 1;
+                0..data.len()
             }
-            let t = TexAtlasItem { x: total_width, y: 0_u32, width, height };
-            log_info(format!("Texture: {} {} {}x{}", &t.x, &t.y, &t.width, &t.height).as_str());
-            textures.push(t);
-            total_width += width;
+            BufferUpdate::Update { range } => range
+        };
+        self.gl.buffer_sub_data_with_i32_and_u8_array(WebGlRenderingContext::ARRAY_BUFFER, range.start as i32, &data[range.clone()]);
+        self.stats.uploaded_bytes += range.len();
+    }
+
+    /// Replaces contents of the bound index buffer.
+    fn upload_indices(gl: &GlContext, indices: &Staging<u16>) {
+        gl.buffer_data_with_u8_array(WebGlRenderingContext::ELEMENT_ARRAY_BUFFER, indices.as_bytes(), WebGlRenderingContext::STATIC_DRAW);
+    }
+
+    /// Points attributes at the first sprite of a batch, as there is neither base vertex (WebGL 1)
+    /// nor base instance (WebGL 2) for draw calls.
+    fn bind_attributes(&self, start: usize) {
+        let layout = &self.objects.layout;
+        let base = layout.sprite_stride * start as i32;
+        for attribute in layout.attributes.iter() {
+            self.gl.vertex_attrib_pointer_with_i32(attribute.location, attribute.size, WebGlRenderingContext::FLOAT,
+                                                   attribute.normalized, layout.stride, base + attribute.offset);
         }
-        total_height = make_power_2(total_height);
-        total_width = make_power_2(total_width);
+    }
 
-        let canvas = document.create_element("canvas")?;
-        let canvas: HtmlCanvasElement = canvas.dyn_into::<HtmlCanvasElement>().unwrap();
-        canvas.set_width(total_width);
-        canvas.set_height(total_height);
-        let context = canvas.get_context("2d")?.unwrap();
-        let context = context.dyn_into::<CanvasRenderingContext2d>().unwrap();
+    /// Makes sure there are `EFFECT_TARGETS` render targets of the given size.
+    fn prepare_targets(&mut self, width: u32, height: u32) -> Result<(), JsValue> {
+        let ready = self.objects.targets.len() == EFFECT_TARGETS
+            && self.objects.targets.iter().all(|target| target.width == width && target.height == height);
+        if ready {
+            return Ok(());
+        }
+        self.delete_targets();
+        for _ in 0..EFFECT_TARGETS {
+            let target = self.create_target(width, height)?;
+            self.objects.targets.push(target);
+        }
+        Ok(())
+    }
 
-        for (index, image) in images.iter().enumerate() {
-            let tex = &textures[index];
-            context.draw_image_with_image_bitmap_and_sw_and_sh_and_dx_and_dy_and_dw_and_dh(
-                image, 0_f64, 0_f64, image.width() as f64, image.height() as f64,
-                tex.x as f64, tex.y as f64, tex.width as f64, tex.height as f64)?;
+    fn create_target(&self, width: u32, height: u32) -> Result<RenderTarget, JsValue> {
+        let texture = self.gl.create_texture().ok_or("Unable to create texture")?;
+        self.gl.bind_texture(WebGlRenderingContext::TEXTURE_2D, Some(&texture));
+        self.gl.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_u8_array(
+            WebGlRenderingContext::TEXTURE_2D, 0, WebGlRenderingContext::RGBA as i32, width as i32, height as i32, 0,
+            WebGlRenderingContext::RGBA, WebGlRenderingContext::UNSIGNED_BYTE, None)?;
+        // passes read whole pixels, clamped at the edges
+        for (pname, param) in [(WebGlRenderingContext::TEXTURE_MAG_FILTER, WebGlRenderingContext::NEAREST),
+                               (WebGlRenderingContext::TEXTURE_MIN_FILTER, WebGlRenderingContext::NEAREST),
+                               (WebGlRenderingContext::TEXTURE_WRAP_S, WebGlRenderingContext::CLAMP_TO_EDGE),
+                               (WebGlRenderingContext::TEXTURE_WRAP_T, WebGlRenderingContext::CLAMP_TO_EDGE)].iter() {
+            self.gl.tex_parameteri(WebGlRenderingContext::TEXTURE_2D, *pname, *param as i32);
         }
+        let framebuffer = match self.gl.create_framebuffer() {
+            Some(framebuffer) => framebuffer,
+            None => {
+                self.gl.delete_texture(Some(&texture));
+                return Err(JsValue::from("Unable to create framebuffer"));
+            }
+        };
+        self.gl.bind_framebuffer(WebGlRenderingContext::FRAMEBUFFER, Some(&framebuffer));
+        self.gl.framebuffer_texture_2d(WebGlRenderingContext::FRAMEBUFFER, WebGlRenderingContext::COLOR_ATTACHMENT0,
+                                       WebGlRenderingContext::TEXTURE_2D, Some(&texture), 0);
+        self.gl.bind_framebuffer(WebGlRenderingContext::FRAMEBUFFER, None);
+        Ok(RenderTarget { framebuffer, texture, width, height })
+    }
 
-        let tex: WebGlTexture = self.gl.create_texture().ok_or("Unable to create texture")?;
-        self.gl.bind_texture(WebGlRenderingContext::TEXTURE_2D, Some(&tex));
-        self.gl.tex_image_2d_with_u32_and_u32_and_canvas(WebGlRenderingContext::TEXTURE_2D, 0,
-                                                         WebGlRenderingContext::RGBA as i32,
-                                                         WebGlRenderingContext::RGBA,
-                                                         WebGlRenderingContext::UNSIGNED_BYTE,
-                                                         &canvas)?;
-        self.gl.tex_parameteri(WebGlRenderingContext::TEXTURE_2D, WebGlRenderingContext::TEXTURE_MAG_FILTER, WebGlRenderingContext::LINEAR as i32);
-        self.gl.tex_parameteri(WebGlRenderingContext::TEXTURE_2D, WebGlRenderingContext::TEXTURE_MIN_FILTER, WebGlRenderingContext::LINEAR_MIPMAP_LINEAR as i32);
-        self.gl.generate_mipmap(WebGlRenderingContext::TEXTURE_2D);
-        Ok(TextureAtlas { items: textures, width: total_width, height: total_height })
-    }
-
-    pub fn create_texture_with_canvases(&self, document: &Document, canvases: &Vec<HtmlCanvasElement>) -> Result<TextureAtlas, JsValue> {
-        let mut textures: Vec<TexAtlasItem> = Vec::with_capacity(canvases.len());
-        let mut total_height = 0_u32;
-        let mut total_width = 0_u32;
-        for canvas in canvases.iter() {
-            let height = canvas.height() as u32;
-            let width = canvas.width() as u32;
-            if total_height < height {
-                total_height = height;
+    fn delete_targets(&mut self) {
+        for target in self.objects.targets.drain(..) {
+            self.gl.delete_framebuffer(Some(&target.framebuffer));
+            self.gl.delete_texture(Some(&target.texture));
+        }
+    }
+
+    /// Draws into the render target `target`, or the canvas for `None`, cleared to `color` when given.
+    fn bind_target(&self, target: Option<usize>, clear: Option<[f32; 4]>) {
+        let framebuffer = target.map(|target| &self.objects.targets[target].framebuffer);
+        self.gl.bind_framebuffer(WebGlRenderingContext::FRAMEBUFFER, framebuffer);
+        if let Some([r, g, b, a]) = clear {
+            self.gl.clear_color(r, g, b, a);
+            self.gl.clear(WebGlRenderingContext::COLOR_BUFFER_BIT);
+        }
+    }
+
+    /// Runs the passes of `effects` on the content of target `input`. Intermediate passes replace
+    /// pixels of the other targets, the last one is blended over `output` like a premultiplied sprite.
+    fn run_effects(&mut self, effects: &[PostEffect], input: usize, output: Option<usize>) {
+        let passes: usize = effects.iter().map(|effect| effect.passes().len()).sum();
+        // the input is free again once the effect reading it is done
+        let scratch = [input, EFFECT_TARGETS - 2, EFFECT_TARGETS - 1];
+        let (width, height) = (self.objects.targets[input].width, self.objects.targets[input].height);
+        self.begin_passes();
+        let mut current = input;
+        let mut done = 0;
+        for effect in effects.iter() {
+            let source = current;
+            for pass in effect.passes().iter() {
+                done += 1;
+                let destination = if done == passes {
+                    self.gl.enable(WebGlRenderingContext::BLEND);
+                    self.gl.blend_func(WebGlRenderingContext::ONE, WebGlRenderingContext::ONE_MINUS_SRC_ALPHA);
+                    output
+                } else {
+                    self.gl.disable(WebGlRenderingContext::BLEND);
+                    scratch.iter().copied().find(|&target| target != current && target != source)
+                };
+                self.bind_target(destination, None);
+                let program = &self.objects.pass_programs[pass.program()];
+                self.gl.use_program(Some(&program.program));
+                self.gl.uniform2f(program.size.as_ref(), width as f32, height as f32);
+                self.gl.uniform4fv_with_f32_array(program.params.as_ref(), &pass.params());
+                self.gl.active_texture(WebGlRenderingContext::TEXTURE1);
+                self.gl.bind_texture(WebGlRenderingContext::TEXTURE_2D, Some(&self.objects.targets[source].texture));
+                self.gl.active_texture(WebGlRenderingContext::TEXTURE0);
+                self.gl.bind_texture(WebGlRenderingContext::TEXTURE_2D, Some(&self.objects.targets[current].texture));
+                self.gl.draw_arrays(WebGlRenderingContext::TRIANGLE_STRIP, 0, 4);
+                self.stats.post_passes += 1;
+                current = destination.unwrap_or(current);
             }
-            let t = TexAtlasItem { x: total_width, y: 0_u32, width, height };
-            log_info(format!("Texture: {} {} {}x{}", &t.x, &t.y, &t.width, &t.height).as_str());
-            textures.push(t);
-            total_width += width;
         }
-        total_height = make_power_2(total_height);
-        total_width = make_power_2(total_width);
+        self.end_passes();
+    }
+
+    /// Draws gradients and images of the background over the cleared frame.
+    fn draw_background(&self, atlas: &TextureAtlas, width: u32, height: u32) {
+        let backdrop = match self.background.backdrop(atlas) {
+            Some(backdrop) => backdrop,
+            None => return
+        };
+        let texture = backdrop.page.and_then(|page| self.objects.resources.textures.get(atlas.pages[page].texture));
+        self.begin_passes();
+        self.gl.blend_func(WebGlRenderingContext::ONE, WebGlRenderingContext::ONE_MINUS_SRC_ALPHA);
+        let program = &self.objects.background_programs[backdrop.program];
+        self.gl.use_program(Some(&program.program));
+        self.gl.uniform2f(program.size.as_ref(), width as f32, height as f32);
+        self.gl.uniform4fv_with_f32_array(program.params.as_ref(), &backdrop.params);
+        self.gl.bind_texture(WebGlRenderingContext::TEXTURE_2D, texture);
+        self.gl.draw_arrays(WebGlRenderingContext::TRIANGLE_STRIP, 0, 4);
+        self.end_passes();
+    }
+
+    /// Points the first attribute at the full-screen quad of passes.
+    fn begin_passes(&self) {
+        self.gl.bind_buffer(WebGlRenderingContext::ARRAY_BUFFER, Some(&self.objects.pass_buffer));
+        self.gl.vertex_attrib_pointer_with_i32(0, 2, WebGlRenderingContext::FLOAT, false, 0, 0);
+        if let GlContext::WebGl2(gl) = &self.gl {
+            gl.vertex_attrib_divisor(0, 0);
+        }
+    }
 
-        let canvas = document.create_element("canvas")?;
-        let canvas: HtmlCanvasElement = canvas.dyn_into::<HtmlCanvasElement>().unwrap();
-        canvas.set_width(total_width);
-        canvas.set_height(total_height);
-        let context = canvas.get_context("2d")?.unwrap();
-        let context = context.dyn_into::<CanvasRenderingContext2d>().unwrap();
+    /// Back to the sprite state after passes.
+    fn end_passes(&self) {
+        self.gl.enable(WebGlRenderingContext::BLEND);
+        self.gl.bind_buffer(WebGlRenderingContext::ARRAY_BUFFER, Some(&self.objects.vertices_buffer));
+        if let GlContext::WebGl2(gl) = &self.gl {
+            gl.vertex_attrib_divisor(0, 1);
+        }
+    }
 
-        for (index, canvas) in canvases.iter().enumerate() {
-            let tex = &textures[index];
-            context.draw_image_with_html_canvas_element_and_sw_and_sh_and_dx_and_dy_and_dw_and_dh(
-                canvas, 0_f64, 0_f64, canvas.width() as f64, canvas.height() as f64,
-                tex.x as f64, tex.y as f64, tex.width as f64, tex.height as f64)?;
+    /// Uploads every page as a texture and assigns the textures to the atlas pages.
+    fn upload_pages(&mut self, atlas: &mut TextureAtlas, pages: &[PageContent]) -> Result<(), JsValue> {
+        for (index, page) in pages.iter().enumerate() {
+            let texture = self.upload_texture(page)?;
+            let id = self.objects.resources.textures.insert(texture);
+            atlas.set_page_texture(index, id);
         }
+        Ok(())
+    }
 
+    fn upload_texture(&self, page: &PageContent) -> Result<WebGlTexture, JsValue> {
         let tex: WebGlTexture = self.gl.create_texture().ok_or("Unable to create texture")?;
         self.gl.bind_texture(WebGlRenderingContext::TEXTURE_2D, Some(&tex));
-        self.gl.tex_image_2d_with_u32_and_u32_and_canvas(WebGlRenderingContext::TEXTURE_2D, 0,
-                                                         WebGlRenderingContext::RGBA as i32,
-                                                         WebGlRenderingContext::RGBA,
-                                                         WebGlRenderingContext::UNSIGNED_BYTE,
-                                                         &canvas)?;
+        match &page.canvas {
+            Some(canvas) => self.gl.tex_image_2d_with_canvas(WebGlRenderingContext::TEXTURE_2D, 0,
+                                                             WebGlRenderingContext::RGBA as i32,
+                                                             WebGlRenderingContext::RGBA,
+                                                             WebGlRenderingContext::UNSIGNED_BYTE,
+                                                             canvas)?,
+            None => self.gl.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_u8_array(
+                WebGlRenderingContext::TEXTURE_2D, 0, WebGlRenderingContext::RGBA as i32,
+                page.width as i32, page.height as i32, 0,
+                WebGlRenderingContext::RGBA, WebGlRenderingContext::UNSIGNED_BYTE, None)?
+        }
+        for (x, y, image) in page.pixels.iter() {
+            self.gl.tex_sub_image_2d_with_i32_and_i32_and_u32_and_type_and_opt_u8_array(
+                WebGlRenderingContext::TEXTURE_2D, 0, *x as i32, *y as i32, image.width as i32, image.height as i32,
+                WebGlRenderingContext::RGBA, WebGlRenderingContext::UNSIGNED_BYTE, Some(&image.pixels))?;
+        }
         self.gl.tex_parameteri(WebGlRenderingContext::TEXTURE_2D, WebGlRenderingContext::TEXTURE_MAG_FILTER, WebGlRenderingContext::LINEAR as i32);
         self.gl.tex_parameteri(WebGlRenderingContext::TEXTURE_2D, WebGlRenderingContext::TEXTURE_MIN_FILTER, WebGlRenderingContext::LINEAR_MIPMAP_LINEAR as i32);
         self.gl.generate_mipmap(WebGlRenderingContext::TEXTURE_2D);
-        Ok(TextureAtlas { items: textures, width: total_width, height: total_height })
+        Ok(tex)
+    }
+}
+
+impl RenderBackend for Renderer {
+    fn create_atlas(&mut self, sources: &[TextureSource]) -> Result<TextureAtlas, JsValue> {
+        let (mut atlas, pages) = compose_atlas(sources, &self.atlas_config)?;
+        self.upload_pages(&mut atlas, &pages)?;
+        Ok(atlas)
     }
 
-    pub fn render(&self, projection: &Projection, sprites: &Vec<Sprite>, atlas: &TextureAtlas) {
-        self.gl.clear_color(0.0, 0.0, 0.0, 1.0);
-        self.gl.clear(WebGlRenderingContext::COLOR_BUFFER_BIT);
+    fn set_atlas_config(&mut self, config: AtlasConfig) {
+        self.atlas_config = config;
+    }
+
+    fn set_batch_order(&mut self, order: BatchOrder) {
+        self.batch_order = order;
+    }
+
+    fn set_layer_visible(&mut self, layer: Layer, visible: bool) {
+        self.visible_layers.set(layer, visible);
+    }
 
-        let location = self.gl.get_uniform_location(&self.program, "u_matrix");
-        self.gl.uniform_matrix3fv_with_f32_array(location.as_ref(), false, &projection.matrix);
+    fn add_shader(&mut self, shader: Shader) -> Result<ShaderId, JsValue> {
+        let program = Renderer::create_program(&self.gl, &shader)?;
+        match self.materials.add_shader(shader) {
+            Ok(id) => {
+                self.objects.resources.programs.insert(program.program.clone());
+                self.objects.programs.push(program);
+                Ok(id)
+            }
+            Err(e) => {
+                self.gl.delete_program(Some(&program.program));
+                Err(JsValue::from(e))
+            }
+        }
+    }
+
+    fn add_material(&mut self, shader: ShaderId) -> Result<MaterialId, JsValue> {
+        Ok(self.materials.add_material(shader)?)
+    }
+
+    fn set_uniform(&mut self, material: MaterialId, name: &str, value: UniformValue) -> Result<(), JsValue> {
+        Ok(self.materials.set_uniform(material, name, value)?)
+    }
+
+    fn materials(&self) -> &Materials {
+        &self.materials
+    }
+
+    fn set_post_effects(&mut self, effects: Vec<PostEffect>) {
+        self.effects.frame = effects;
+    }
+
+    fn set_layer_effects(&mut self, layer: Layer, effects: Vec<PostEffect>) {
+        self.effects.set_layer(layer, effects);
+    }
+
+    fn set_background(&mut self, background: Background) {
+        self.background = background;
+    }
+
+    fn delete_atlas(&mut self, atlas: TextureAtlas) {
+        for page in atlas.pages.iter() {
+            if let Some(texture) = self.objects.resources.textures.remove(page.texture) {
+                self.gl.delete_texture(Some(&texture));
+            }
+        }
+    }
+
+    fn resources(&self) -> ResourceCounts {
+        ResourceCounts {
+            textures: self.objects.resources.textures.len(),
+            buffers: self.objects.resources.buffers.len(),
+            programs: self.objects.resources.programs.len(),
+        }
+    }
+
+    fn resize(&mut self, width: u32, height: u32) {
+        if let Some(canvas) = self.gl.canvas().and_then(|canvas| canvas.dyn_into::<HtmlCanvasElement>().ok()) {
+            canvas.set_width(width);
+            canvas.set_height(height);
+        }
+        self.gl.viewport(0, 0, width as i32, height as i32);
+    }
+
+    fn restore(&mut self) -> Result<(), JsValue> {
+        // objects of a lost context are invalid already, those left by a failed restore are not
+        if self.gl.is_context_lost() {
+            self.objects.targets.clear();
+            self.objects.resources = GlResources::create();
+        } else {
+            self.delete_targets();
+            self.objects.resources.delete(&self.gl);
+        }
+        self.objects = Renderer::create_objects(&self.gl, &self.materials)?;
+        self.vertices.reset();
+        self.indexed_quads = 0;
+        log_info("Renderer restored");
+        Ok(())
+    }
+
+    fn frame_stats(&self) -> FrameStats {
+        self.stats
+    }
+
+    fn render(&mut self, projection: &Projection, sprites: &[Sprite], atlas: &TextureAtlas) {
+        self.stats = FrameStats::default();
         self.gl.viewport(0, 0, projection.canvas_width as i32, projection.canvas_height as i32);
 
-        self.update_buffers(sprites, atlas);
-        self.gl.bind_buffer(WebGlRenderingContext::ELEMENT_ARRAY_BUFFER, Some(&self.indices_buffer));
-        self.gl.draw_elements_with_i32(WebGlRenderingContext::TRIANGLES, 6 * sprites.len() as i32, WebGlRenderingContext::UNSIGNED_SHORT, 0);
-        log_debug("Renderer: render completed");
+        let visible = projection.visible_rect();
+        let (draw_order, batches) = batch_visible_sprites(sprites, atlas, self.batch_order, &visible, self.visible_layers);
+        let has_effects = !self.effects.frame.is_empty()
+            || batches.iter().any(|batch| !self.effects.layer(batch.layer).is_empty());
+        let has_effects = has_effects && match self.prepare_targets(projection.canvas_width, projection.canvas_height) {
+            Ok(()) => true,
+            Err(e) => {
+                log_error(format!("Renderer: post effects skipped, {:?}", &e).as_str());
+                false
+            }
+        };
+        // with frame effects the canvas only gets the result of the last pass
+        let frame_target = if has_effects && !self.effects.frame.is_empty() {
+            self.bind_target(None, Some([0.0, 0.0, 0.0, 0.0]));
+            Some(FRAME_TARGET)
+        } else {
+            None
+        };
+        self.bind_target(frame_target, Some(self.background.clear_color()));
+        self.draw_background(atlas, projection.canvas_width, projection.canvas_height);
+        self.stats.drawn_sprites = draw_order.len();
+        self.stats.culled_sprites = sprites.len() - draw_order.len();
+        match &self.gl {
+            GlContext::WebGl1(_) => self.update_buffers(sprites, &draw_order, atlas),
+            GlContext::WebGl2(_) => self.update_instances(sprites, &draw_order, atlas),
+        }
+        self.gl.bind_buffer(WebGlRenderingContext::ELEMENT_ARRAY_BUFFER, Some(&self.objects.indices_buffer));
+        let mut current_material = None;
+        let mut current_blend = None;
+        let mut current_layer = None;
+        // layer whose sprites are being drawn into `LAYER_TARGET` for its effects
+        let mut effect_layer: Option<Layer> = None;
+        for batch in batches.iter() {
+            if current_layer != Some(batch.layer) {
+                if let Some(layer) = effect_layer.take() {
+                    let effects = self.effects.layer(layer).to_vec();
+                    self.run_effects(&effects, LAYER_TARGET, frame_target);
+                    self.bind_target(frame_target, None);
+                    current_material = None;
+                    current_blend = None;
+                }
+                if has_effects && !self.effects.layer(batch.layer).is_empty() {
+                    self.bind_target(Some(LAYER_TARGET), Some([0.0, 0.0, 0.0, 0.0]));
+                    effect_layer = Some(batch.layer);
+                }
+                current_layer = Some(batch.layer);
+            }
+            let material = match self.materials.material(batch.material) {
+                Some(material) => material,
+                None => continue
+            };
+            if current_material != Some(batch.material) {
+                self.use_material(material, projection);
+                current_material = Some(batch.material);
+            }
+            let program = &self.objects.programs[material.shader];
+            if current_blend != Some(batch.blend) {
+                let (source, destination) = batch.blend.factors();
+                self.gl.blend_func(source, destination);
+                current_blend = Some(batch.blend);
+            }
+            self.gl.uniform1f(program.premultiplied.as_ref(), if batch.blend.premultiplied() { 1.0 } else { 0.0 });
+            let page = &atlas.pages[batch.page];
+            self.gl.uniform2f(program.texel_size.as_ref(), 1.0 / page.width as f32, 1.0 / page.height as f32);
+            let texture = self.objects.resources.textures.get(page.texture);
+            self.gl.bind_texture(WebGlRenderingContext::TEXTURE_2D, texture);
+            self.bind_attributes(batch.start);
+            match &self.gl {
+                GlContext::WebGl1(gl) => {
+                    gl.draw_elements_with_i32(WebGlRenderingContext::TRIANGLES, 6 * batch.count as i32,
+                                              WebGlRenderingContext::UNSIGNED_SHORT, 0);
+                }
+                GlContext::WebGl2(gl) => {
+                    gl.draw_elements_instanced_with_i32(WebGl2RenderingContext::TRIANGLES, 6,
+                                                        WebGl2RenderingContext::UNSIGNED_SHORT, 0, batch.count as i32);
+                }
+            }
+            self.stats.draw_calls += 1;
+        }
+        if let Some(layer) = effect_layer {
+            let effects = self.effects.layer(layer).to_vec();
+            self.run_effects(&effects, LAYER_TARGET, frame_target);
+        }
+        if frame_target.is_some() {
+            let effects = self.effects.frame.clone();
+            self.run_effects(&effects, FRAME_TARGET, None);
+        }
+        log_debug(format!("Renderer: render completed, {:?}", self.stats).as_str());
+    }
+}
+
+/// Content of an atlas page: a canvas with the DOM sources drawn on it, if the page has any,
+/// and extruded pixel sources with positions of their top left corners.
+pub struct PageContent {
+    pub width: u32,
+    pub height: u32,
+    pub canvas: Option<HtmlCanvasElement>,
+    pub pixels: Vec<(u32, u32, RgbaImage)>,
+}
+
+impl Drop for Renderer {
+    fn drop(&mut self) {
+        self.delete_targets();
+        self.objects.resources.delete(&self.gl);
+        log_info("Renderer resources deleted");
+    }
+}
+
+fn create_atlas_canvas(document: &Document, page: &AtlasPage) -> Result<(HtmlCanvasElement, CanvasRenderingContext2d), JsValue> {
+    let canvas = document.create_element("canvas")?;
+    let canvas: HtmlCanvasElement = canvas.dyn_into::<HtmlCanvasElement>().unwrap();
+    canvas.set_width(page.width);
+    canvas.set_height(page.height);
+    let context = canvas.get_context("2d")?.unwrap();
+    let context = context.dyn_into::<CanvasRenderingContext2d>().unwrap();
+    // extrusion stretches single pixel strips, smoothing would blur them
+    context.set_image_smoothing_enabled(false);
+    Ok((canvas, context))
+}
+
+/// Draws a DOM source at its atlas item, then stretches its outermost pixels
+/// over `extrusion` pixels around it.
+fn draw_extruded(context: &CanvasRenderingContext2d, source: &TextureSource, tex: &TexAtlasItem, extrusion: u32) -> Result<(), JsValue> {
+    let (x, y, w, h) = (tex.x as f64, tex.y as f64, tex.width as f64, tex.height as f64);
+    source.draw(context, (0_f64, 0_f64, w, h), (x, y, w, h))?;
+    if extrusion == 0 {
+        return Ok(());
     }
+    let e = extrusion as f64;
+    // edges
+    source.draw(context, (0_f64, 0_f64, 1_f64, h), (x - e, y, e, h))?;
+    source.draw(context, (w - 1_f64, 0_f64, 1_f64, h), (x + w, y, e, h))?;
+    source.draw(context, (0_f64, 0_f64, w, 1_f64), (x, y - e, w, e))?;
+    source.draw(context, (0_f64, h - 1_f64, w, 1_f64), (x, y + h, w, e))?;
+    // corners
+    source.draw(context, (0_f64, 0_f64, 1_f64, 1_f64), (x - e, y - e, e, e))?;
+    source.draw(context, (w - 1_f64, 0_f64, 1_f64, 1_f64), (x + w, y - e, e, e))?;
+    source.draw(context, (0_f64, h - 1_f64, 1_f64, 1_f64), (x - e, y + h, e, e))?;
+    source.draw(context, (w - 1_f64, h - 1_f64, 1_f64, 1_f64), (x + w, y + h, e, e))
+}
+
+fn to_js_error(e: PackError) -> JsValue {
+    JsValue::from(e.to_string())
 }
 
-fn make_power_2(v: u32) -> u32 {
-    let mut p = 1_u32;
-    while p < v {
-        p = p * 2;
+/// Packs sources into an atlas and prepares the content of its pages. DOM sources are drawn
+/// on a canvas per page, pixel sources are kept aside to be copied without a canvas.
+pub fn compose_atlas(sources: &[TextureSource], config: &AtlasConfig) -> Result<(TextureAtlas, Vec<PageContent>), JsValue> {
+    let sizes: Vec<(u32, u32)> = sources.iter().map(|source| source.size()).collect();
+    let atlas = TextureAtlas::pack(&sizes, config).map_err(to_js_error)?;
+    let mut pages: Vec<PageContent> = atlas.pages.iter()
+        .map(|page| PageContent { width: page.width, height: page.height, canvas: None, pixels: Vec::new() })
+        .collect();
+    let mut contexts: Vec<Option<CanvasRenderingContext2d>> = vec![None; pages.len()];
+    for (index, source) in sources.iter().enumerate() {
+        let item = &atlas.items[index];
+        if let Some(pixels) = source.pixels() {
+            let e = config.extrusion;
+            pages[item.page].pixels.push((item.x - e, item.y - e, pixels.extruded(e)));
+            continue;
+        }
+        if contexts[item.page].is_none() {
+            let document = web_sys::window().and_then(|window| window.document()).ok_or("no document")?;
+            let (canvas, context) = create_atlas_canvas(&document, &atlas.pages[item.page])?;
+            pages[item.page].canvas = Some(canvas);
+            contexts[item.page] = Some(context);
+        }
+        if let Some(context) = &contexts[item.page] {
+            draw_extruded(context, source, item, config.extrusion)?;
+        }
     }
-    p
+    Ok((atlas, pages))
 }
 
-static FRAGMENT_SHADER: &str = "precision mediump float; uniform sampler2D u_image; varying vec2 v_texCoord; varying float v_alpha; \
-void main() {gl_FragColor = texture2D(u_image, v_texCoord); gl_FragColor.a = gl_FragColor.a * v_alpha;}";
-static VERTEX_SHADER: &str = "attribute vec2 a_position; attribute vec2 a_texCoord; attribute float a_alpha; uniform mat3 u_matrix; varying vec2 v_texCoord; varying float v_alpha; \
-void main() {gl_Position = vec4((u_matrix * vec3(a_position, 1)).xy, 0, 1); v_texCoord = a_texCoord; v_alpha = a_alpha;}";
\ No newline at end of file
+/// Bytes per quad vertex: position, texture coordinates, alpha, both tints and the attributes.
+pub const QUAD_STRIDE: usize = 17 * 4;
+/// Bytes per sprite instance: position, pivot, size, rotation, alpha, the texture rectangle,
+/// the first two rows of the transform, scale, both tints and the attributes.
+pub const INSTANCE_STRIDE: usize = 32 * 4;
+
+/// Name, size in floats, normalized flag and byte offset of a vertex attribute.
+pub type AttributeSpec = (&'static str, i32, bool, i32);
+pub static QUAD_ATTRIBUTES: [AttributeSpec; 6] = [("a_position", 2, false, 0), ("a_texCoord", 2, true, 8), ("a_alpha", 1, true, 16),
+    ("a_tint", 4, false, 20), ("a_tintAdd", 4, false, 36), ("a_attributes", 4, false, 52)];
+pub static INSTANCE_ATTRIBUTES: [AttributeSpec; 12] = [("a_position", 2, false, 0), ("a_pivot", 2, false, 8),
+    ("a_size", 2, false, 16), ("a_rotation", 1, false, 24), ("a_alpha", 1, false, 28), ("a_texRect", 4, false, 32),
+    ("a_transformX", 3, false, 48), ("a_transformY", 3, false, 60), ("a_scale", 2, false, 72),
+    ("a_tint", 4, false, 80), ("a_tintAdd", 4, false, 96), ("a_attributes", 4, false, 112)];
+
+/// Fragment shaders are a header, the uniforms, attribute defines and `shade` of a `Shader`, then `main`.
+/// `main` clamps the tinted color like the color buffer would and premultiplies it for blending.
+pub static FRAGMENT_HEADER: &str = "precision mediump float; uniform sampler2D u_image; uniform vec2 u_texelSize; uniform float u_premultiplied; \
+varying vec2 v_texCoord; varying float v_alpha; varying vec4 v_tint; varying vec4 v_tintAdd; varying vec4 v_attributes;
+vec4 texel(vec2 uv) { vec4 c = texture2D(u_image, uv); return u_premultiplied > 0.5 || c.a == 0.0 ? c : vec4(c.rgb / c.a, c.a); }
+";
+pub static FRAGMENT_MAIN: &str = "void main() {vec4 c = clamp(shade(v_texCoord) * v_tint + v_tintAdd, 0.0, 1.0); \
+if (u_premultiplied < 0.5) { c.rgb *= c.a; } gl_FragColor = c * v_alpha;}";
+pub static VERTEX_SHADER: &str = "attribute vec2 a_position; attribute vec2 a_texCoord; attribute float a_alpha; attribute vec4 a_tint; attribute vec4 a_tintAdd; \
+attribute vec4 a_attributes; uniform mat3 u_matrix; varying vec2 v_texCoord; varying float v_alpha; varying vec4 v_tint; varying vec4 v_tintAdd; \
+varying vec4 v_attributes; void main() {gl_Position = vec4((u_matrix * vec3(a_position, 1)).xy, 0, 1); v_texCoord = a_texCoord; v_alpha = a_alpha; \
+v_tint = a_tint; v_tintAdd = a_tintAdd; v_attributes = a_attributes;}";
+/// Defines the GLSL ES 1.0 lookups `Shader::glsl` may use to their GLSL ES 3.0 names.
+pub static INSTANCED_FRAGMENT_HEADER: &str = "#version 300 es
+#define texture2D texture
+#define texture2DProj textureProj
+precision mediump float; uniform sampler2D u_image; uniform vec2 u_texelSize; uniform float u_premultiplied; in vec2 v_texCoord; in float v_alpha;
+flat in vec4 v_tint; flat in vec4 v_tintAdd; flat in vec4 v_attributes; out vec4 fragColor;
+vec4 texel(vec2 uv) { vec4 c = texture(u_image, uv); return u_premultiplied > 0.5 || c.a == 0.0 ? c : vec4(c.rgb / c.a, c.a); }
+";
+pub static INSTANCED_FRAGMENT_MAIN: &str = "void main() {vec4 c = clamp(shade(v_texCoord) * v_tint + v_tintAdd, 0.0, 1.0);
+if (u_premultiplied < 0.5) { c.rgb *= c.a; } fragColor = c * v_alpha;}";
+/// Expands corner `gl_VertexID` (0..3 clockwise from the top left, as in `Sprite::quad`) of an instance.
+pub static INSTANCED_VERTEX_SHADER: &str = "#version 300 es
+in vec2 a_position; in vec2 a_pivot; in vec2 a_size; in float a_rotation; in float a_alpha; in vec4 a_texRect;
+in vec3 a_transformX; in vec3 a_transformY; in vec2 a_scale; in vec4 a_tint; in vec4 a_tintAdd; in vec4 a_attributes;
+uniform mat3 u_matrix; out vec2 v_texCoord; out float v_alpha; flat out vec4 v_tint; flat out vec4 v_tintAdd; flat out vec4 v_attributes;
+void main() {
+  vec2 corner = vec2(gl_VertexID == 1 || gl_VertexID == 2 ? 1.0 : 0.0, gl_VertexID >= 2 ? 1.0 : 0.0);
+  vec2 local = (corner * a_size - a_pivot) * a_scale;
+  float s = sin(a_rotation); float c = cos(a_rotation);
+  vec3 p = vec3(a_position + vec2(local.x * c - local.y * s, local.x * s + local.y * c), 1);
+  p = vec3(dot(a_transformX, p), dot(a_transformY, p), 1);
+  gl_Position = vec4((u_matrix * p).xy, 0, 1);
+  v_texCoord = mix(a_texRect.xy, a_texRect.zw, corner); v_alpha = a_alpha; v_tint = a_tint; v_tintAdd = a_tintAdd;
+  v_attributes = a_attributes;
+}";
@@ -7,12 +7,69 @@ pub struct ImageLoader {
 
 impl ImageLoader {
     pub fn fetch_image(src: &str) -> Result<JsFuture, JsValue> {
-        let mut opts = RequestInit::new();
-        opts.method("GET");
-        opts.mode(RequestMode::SameOrigin);
+        let opts = RequestInit::new();
+        opts.set_method("GET");
+        opts.set_mode(RequestMode::SameOrigin);
         let request = Request::new_with_str_and_init(src, &opts)?;
         request.headers().set("Accept", "image/png")?;
         let window = web_sys::window().unwrap();
         Ok(JsFuture::from(window.fetch_with_request(&request)))
     }
 }
+
+/// Storage for objects referred to by numeric handles, e.g. GPU textures.
+/// Handles of removed objects are reused.
+pub struct Registry<T> {
+    slots: Vec<Option<T>>,
+    free: Vec<usize>,
+}
+
+impl<T> Registry<T> {
+    pub fn create() -> Registry<T> {
+        Registry { slots: Vec::new(), free: Vec::new() }
+    }
+
+    pub fn insert(&mut self, value: T) -> usize {
+        match self.free.pop() {
+            Some(id) => {
+                self.slots[id] = Some(value);
+                id
+            }
+            None => {
+                self.slots.push(Some(value));
+                self.slots.len() - 1
+            }
+        }
+    }
+
+    pub fn get(&self, id: usize) -> Option<&T> {
+        self.slots.get(id).and_then(|slot| slot.as_ref())
+    }
+
+    pub fn get_mut(&mut self, id: usize) -> Option<&mut T> {
+        self.slots.get_mut(id).and_then(|slot| slot.as_mut())
+    }
+
+    pub fn remove(&mut self, id: usize) -> Option<T> {
+        let value = self.slots.get_mut(id).and_then(|slot| slot.take());
+        if value.is_some() {
+            self.free.push(id);
+        }
+        value
+    }
+
+    /// Removes all objects, returning them for disposal.
+    pub fn drain(&mut self) -> Vec<T> {
+        self.free.clear();
+        self.slots.drain(..).flatten().collect()
+    }
+
+    /// Number of live objects.
+    pub fn len(&self) -> usize {
+        self.slots.len() - self.free.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
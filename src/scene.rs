@@ -0,0 +1,189 @@
+use crate::geom::{Mat3, Point};
+use crate::renderer::Sprite;
+use crate::resource_manager::Registry;
+
+/// Handle of a scene node. Slots of removed nodes are reused, the generation tells a stale
+/// handle apart from the node that took its slot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NodeId {
+    index: usize,
+    generation: u32,
+}
+
+/// Local transform of a node relative to its parent. Applied in the order
+/// scale, skew, rotation, translation.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transform {
+    pub translation: Point,
+    pub rotation: f32,
+    pub scale: Point,
+    /// Shear angles, see `Mat3::skew`.
+    pub skew: Point,
+}
+
+impl Transform {
+    pub fn identity() -> Transform {
+        Transform {
+            translation: Point { x: 0.0, y: 0.0 },
+            rotation: 0.0,
+            scale: Point { x: 1.0, y: 1.0 },
+            skew: Point { x: 0.0, y: 0.0 },
+        }
+    }
+
+    pub fn matrix(&self) -> Mat3 {
+        Mat3::translation(self.translation) * Mat3::rotation(self.rotation) * Mat3::skew(self.skew) * Mat3::scale(self.scale)
+    }
+}
+
+/// Scene graph node: a group of children and optionally a sprite, both placed in the node's space.
+pub struct Node {
+    pub transform: Transform,
+    /// Hidden nodes are skipped with all their descendants.
+    pub visible: bool,
+    /// Order among siblings, lower first. Siblings with equal `z` keep the order they were added in.
+    pub z: i32,
+    pub sprite: Option<Sprite>,
+    parent: Option<NodeId>,
+    children: Vec<NodeId>,
+}
+
+impl Node {
+    pub fn group() -> Node {
+        Node { transform: Transform::identity(), visible: true, z: 0, sprite: None, parent: None, children: Vec::new() }
+    }
+
+    pub fn sprite(sprite: Sprite) -> Node {
+        Node { sprite: Some(sprite), ..Node::group() }
+    }
+
+    pub fn parent(&self) -> Option<NodeId> {
+        self.parent
+    }
+
+    pub fn children(&self) -> &[NodeId] {
+        &self.children
+    }
+}
+
+/// Tree of nodes under a root group, flattened into sprites with world transforms for rendering.
+pub struct Scene {
+    nodes: Registry<Node>,
+    /// Current generation of every slot of `nodes`, increased when its node is removed.
+    generations: Vec<u32>,
+    root: NodeId,
+}
+
+impl Scene {
+    pub fn create() -> Scene {
+        let mut scene = Scene { nodes: Registry::create(), generations: Vec::new(), root: NodeId { index: 0, generation: 0 } };
+        scene.root = scene.insert(Node::group());
+        scene
+    }
+
+    fn insert(&mut self, node: Node) -> NodeId {
+        let index = self.nodes.insert(node);
+        if index == self.generations.len() {
+            self.generations.push(0);
+        }
+        NodeId { index, generation: self.generations[index] }
+    }
+
+    pub fn root(&self) -> NodeId {
+        self.root
+    }
+
+    /// Adds `node` as the last child of `parent`.
+    pub fn add(&mut self, parent: NodeId, mut node: Node) -> Result<NodeId, String> {
+        if self.node(parent).is_none() {
+            return Err(format!("Scene node {:?} does not exist", parent));
+        }
+        node.parent = Some(parent);
+        node.children.clear();
+        let id = self.insert(node);
+        self.node_mut(parent).unwrap().children.push(id);
+        Ok(id)
+    }
+
+    /// Removes the node with its whole subtree. The root is only emptied.
+    pub fn remove(&mut self, id: NodeId) {
+        let node = match self.node_mut(id) {
+            Some(node) => node,
+            None => return
+        };
+        let children = std::mem::take(&mut node.children);
+        let parent = node.parent;
+        for child in children {
+            self.remove(child);
+        }
+        if id == self.root {
+            return;
+        }
+        if let Some(parent) = parent.and_then(|parent| self.node_mut(parent)) {
+            parent.children.retain(|child| *child != id);
+        }
+        self.nodes.remove(id.index);
+        self.generations[id.index] += 1;
+    }
+
+    /// `None` for removed nodes, also after their slot was reused.
+    pub fn node(&self, id: NodeId) -> Option<&Node> {
+        if self.generations.get(id.index) != Some(&id.generation) {
+            return None;
+        }
+        self.nodes.get(id.index)
+    }
+
+    pub fn node_mut(&mut self, id: NodeId) -> Option<&mut Node> {
+        if self.generations.get(id.index) != Some(&id.generation) {
+            return None;
+        }
+        self.nodes.get_mut(id.index)
+    }
+
+    /// Number of nodes including the root.
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Transform from the node's space to the scene (world) space.
+    pub fn world_matrix(&self, id: NodeId) -> Mat3 {
+        let mut matrix = Mat3::identity();
+        let mut current = self.node(id);
+        while let Some(node) = current {
+            matrix = node.transform.matrix() * matrix;
+            current = node.parent.and_then(|parent| self.node(parent));
+        }
+        matrix
+    }
+
+    /// Sprites of visible nodes in drawing order: parents before children, siblings by `z`.
+    /// Every sprite gets the world transform of its node combined with its own `transform`.
+    pub fn flatten(&self) -> Vec<Sprite> {
+        let mut sprites = Vec::new();
+        self.flatten_node(self.root, Mat3::identity(), &mut sprites);
+        sprites
+    }
+
+    fn flatten_node(&self, id: NodeId, parent_matrix: Mat3, sprites: &mut Vec<Sprite>) {
+        let node = match self.node(id) {
+            Some(node) if node.visible => node,
+            _ => return
+        };
+        let matrix = parent_matrix * node.transform.matrix();
+        if let Some(sprite) = &node.sprite {
+            let mut sprite = sprite.clone();
+            sprite.transform = matrix * sprite.transform;
+            sprites.push(sprite);
+        }
+        let mut children = node.children.clone();
+        children.sort_by_key(|child| self.node(*child).map(|child| child.z).unwrap_or(0));
+        for child in children {
+            self.flatten_node(child, matrix, sprites);
+        }
+    }
+}
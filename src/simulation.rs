@@ -0,0 +1,164 @@
+use core::f32::consts::PI;
+
+use crate::geom::Point;
+use crate::random::Rng;
+use crate::renderer::Sprite;
+
+/// How far outside the visible area snowflakes are kept before wrapping around.
+const MARGIN: f32 = 100.0;
+/// Snowflakes per square pixel of the visible area.
+const DENSITY: f32 = 0.0006;
+
+/// Platform independent snowfall physics. The wasm layer feeds it time and pointer events,
+/// everything else (wind, wrap-around, rotation) happens here.
+pub struct Simulation {
+    pub width: f32,
+    pub height: f32,
+    wind: Point,
+    last_pointer: Option<Point>,
+}
+
+impl Simulation {
+    pub fn create(width: f32, height: f32) -> Simulation {
+        Simulation {
+            width,
+            height,
+            wind: Point { x: 0.0, y: 0.0 },
+            last_pointer: None,
+        }
+    }
+
+    pub fn wind(&self) -> Point {
+        self.wind
+    }
+
+    /// Creates snowflakes for the visible area. `textures` holds width and height
+    /// of every snowflake image, sprite texture indices refer to it.
+    pub fn populate(&self, textures: &[(u32, u32)], rng: &mut dyn Rng) -> Vec<Sprite> {
+        let quantity = self.quantity();
+        let mut sprites = Vec::with_capacity(quantity);
+        if textures.is_empty() {
+            return sprites;
+        }
+        for i in 0..quantity {
+            sprites.push(self.flake(i as f32 / quantity as f32, textures, rng));
+        }
+        sprites
+    }
+
+    /// Changes the visible area keeping the existing snowflakes: positions are scaled
+    /// to the new area, then flakes are removed or added to keep the density.
+    pub fn resize(&mut self, sprites: &mut Vec<Sprite>, width: f32, height: f32,
+                  textures: &[(u32, u32)], rng: &mut dyn Rng) {
+        let scale_x = (width + 2.0 * MARGIN) / (self.width + 2.0 * MARGIN);
+        let scale_y = (height + 2.0 * MARGIN) / (self.height + 2.0 * MARGIN);
+        for sprite in sprites.iter_mut() {
+            sprite.position.x = (sprite.position.x + MARGIN) * scale_x - MARGIN;
+            sprite.position.y = (sprite.position.y + MARGIN) * scale_y - MARGIN;
+        }
+        self.width = width;
+        self.height = height;
+
+        // flakes are ordered from far to near, keep that order and the spread of distances
+        let quantity = self.quantity();
+        let count = sprites.len();
+        if quantity < count {
+            let mut index = 0;
+            sprites.retain(|_| {
+                let keep = (index + 1) * quantity / count > index * quantity / count;
+                index += 1;
+                keep
+            });
+        } else if quantity > count && !textures.is_empty() {
+            let extra = quantity - count;
+            let added: Vec<Sprite> = (0..extra)
+                .map(|i| self.flake((i as f32 + 0.5) / extra as f32, textures, rng))
+                .collect();
+            let mut merged = Vec::with_capacity(quantity);
+            let mut existing = sprites.drain(..).peekable();
+            let mut added = added.into_iter().peekable();
+            while let (Some(a), Some(b)) = (existing.peek(), added.peek()) {
+                if a.width <= b.width {
+                    merged.push(existing.next().unwrap());
+                } else {
+                    merged.push(added.next().unwrap());
+                }
+            }
+            merged.extend(existing);
+            merged.extend(added);
+            *sprites = merged;
+        }
+    }
+
+    fn quantity(&self) -> usize {
+        (self.width * self.height * DENSITY) as usize
+    }
+
+    /// Creates a snowflake at `distance` from 0 (far, small and faint) to 1 (near).
+    fn flake(&self, distance: f32, textures: &[(u32, u32)], rng: &mut dyn Rng) -> Sprite {
+        let tex_index = (rng.range(0.0, textures.len() as f32) as usize).min(textures.len() - 1);
+        let (image_width, image_height) = textures[tex_index];
+        let size = 15.0 + distance * 80.0;
+        let sprite_width = size;
+        let sprite_height = size * image_height as f32 / image_width as f32;
+        let position = Point {
+            x: rng.range(-MARGIN, MARGIN + self.width),
+            y: rng.range(-MARGIN, MARGIN + self.height),
+        };
+        Sprite {
+            texture: tex_index,
+            position,
+            pivot: Point { x: sprite_width * 0.5, y: sprite_height * 0.5 },
+            rotation: rng.range(0.0, 2.0 * PI),
+            width: sprite_width,
+            height: sprite_height,
+            alpha: 0.25 + distance * 0.4,
+            ..Sprite::default()
+        }
+    }
+
+    /// Advances snowflakes by `delta_seconds`. `pointer` is the current drag position, if any;
+    /// its movement since the previous step pushes the wind.
+    pub fn step(&mut self, sprites: &mut [Sprite], delta_seconds: f32, pointer: Option<Point>) {
+        let width = self.width;
+        let height = self.height;
+
+        let pointer_delta = match pointer {
+            Some(p) => p - self.last_pointer.unwrap_or(p),
+            None => Point { x: 0.0, y: 0.0 }
+        };
+        let pointer_delta = Point { x: pointer_delta.x / width, y: pointer_delta.y / height };
+        self.last_pointer = pointer;
+        let wind_factor = if delta_seconds > 1.0 { 0.0 } else { 1.0 - 0.5 * delta_seconds };
+        self.wind = (self.wind + pointer_delta * 350.0 * delta_seconds) * wind_factor;
+
+        let wind = self.wind;
+        for (i, sprite) in sprites.iter_mut().enumerate() {
+            sprite.position.x += delta_seconds * (wind.x + 0.1) * sprite.width;
+            if sprite.position.x > width + MARGIN {
+                sprite.position.x -= width + 2.0 * MARGIN;
+            }
+            if sprite.position.x < -MARGIN {
+                sprite.position.x += width + 2.0 * MARGIN;
+            }
+            sprite.position.y += delta_seconds * (wind.y * 0.5 + 0.33) * sprite.width;
+            if sprite.position.y > height + MARGIN {
+                sprite.position.y -= height + 2.0 * MARGIN;
+            }
+            if sprite.position.y < -MARGIN {
+                sprite.position.y += height + 2.0 * MARGIN;
+            }
+            let rotation_speed = match i % 5 {
+                0 => -0.05_f32,
+                1 => -0.025_f32,
+                2 => 0.0_f32,
+                3 => 0.025_f32,
+                _ => 0.05_f32
+            };
+            sprite.rotation += delta_seconds * PI * rotation_speed;
+            if sprite.rotation > 2.0 * PI {
+                sprite.rotation -= 2.0 * PI;
+            }
+        }
+    }
+}
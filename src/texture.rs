@@ -0,0 +1,156 @@
+use wasm_bindgen::JsValue;
+use web_sys::{CanvasRenderingContext2d, HtmlCanvasElement, HtmlImageElement, ImageBitmap, ImageData};
+
+/// Image stored as RGBA bytes, row by row from the top left corner.
+#[derive(Clone)]
+pub struct RgbaImage {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl RgbaImage {
+    pub fn create(width: u32, height: u32) -> RgbaImage {
+        RgbaImage { width, height, pixels: vec![0_u8; (width * height * 4) as usize] }
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+        let i = ((y * self.width + x) * 4) as usize;
+        [self.pixels[i], self.pixels[i + 1], self.pixels[i + 2], self.pixels[i + 3]]
+    }
+
+    pub fn set_pixel(&mut self, x: u32, y: u32, color: [u8; 4]) {
+        let i = ((y * self.width + x) * 4) as usize;
+        self.pixels[i..i + 4].copy_from_slice(&color);
+    }
+
+    pub(crate) fn fill(&mut self, color: [u8; 4]) {
+        for pixel in self.pixels.chunks_exact_mut(4) {
+            pixel.copy_from_slice(&color);
+        }
+    }
+
+    /// Copies `image` into this one with its top left corner at `x`, `y`.
+    pub(crate) fn blit(&mut self, image: &RgbaImage, x: u32, y: u32) {
+        let row = (image.width * 4) as usize;
+        for line in 0..image.height {
+            let src = (line * image.width * 4) as usize;
+            let dst = (((y + line) * self.width + x) * 4) as usize;
+            self.pixels[dst..dst + row].copy_from_slice(&image.pixels[src..src + row]);
+        }
+    }
+
+    /// Copy with color channels multiplied by alpha, the way atlas pages are stored.
+    pub fn premultiplied(&self) -> RgbaImage {
+        let mut image = self.clone();
+        for pixel in image.pixels.chunks_exact_mut(4) {
+            let a = pixel[3] as u32;
+            for c in pixel[..3].iter_mut() {
+                *c = ((*c as u32 * a + 127) / 255) as u8;
+            }
+        }
+        image
+    }
+
+    /// Copy with color channels divided by alpha, the inverse of `premultiplied`.
+    pub fn unpremultiplied(&self) -> RgbaImage {
+        let mut image = self.clone();
+        for pixel in image.pixels.chunks_exact_mut(4) {
+            let a = pixel[3] as u32;
+            if a == 0 || a == 255 {
+                continue;
+            }
+            for c in pixel[..3].iter_mut() {
+                *c = ((*c as u32 * 255 + a / 2) / a).min(255) as u8;
+            }
+        }
+        image
+    }
+
+    /// Copy of the image surrounded by `extrusion` pixels repeating its outermost ones.
+    pub fn extruded(&self, extrusion: u32) -> RgbaImage {
+        let e = extrusion as i64;
+        let mut image = RgbaImage::create(self.width + 2 * extrusion, self.height + 2 * extrusion);
+        for y in 0..image.height {
+            let src_y = (y as i64 - e).clamp(0, self.height as i64 - 1) as u32;
+            for x in 0..image.width {
+                let src_x = (x as i64 - e).clamp(0, self.width as i64 - 1) as u32;
+                image.set_pixel(x, y, self.pixel(src_x, src_y));
+            }
+        }
+        image
+    }
+
+    /// Bilinear sample with clamp to edge, `u` and `v` are normalized texture coordinates.
+    pub(crate) fn sample(&self, u: f32, v: f32) -> [f32; 4] {
+        let x = u * self.width as f32 - 0.5;
+        let y = v * self.height as f32 - 0.5;
+        let x0 = x.floor();
+        let y0 = y.floor();
+        let fx = x - x0;
+        let fy = y - y0;
+        let clamp_x = |x: f32| (x.max(0.0) as u32).min(self.width - 1);
+        let clamp_y = |y: f32| (y.max(0.0) as u32).min(self.height - 1);
+        let (xa, xb) = (clamp_x(x0), clamp_x(x0 + 1.0));
+        let (ya, yb) = (clamp_y(y0), clamp_y(y0 + 1.0));
+        let p00 = self.pixel(xa, ya);
+        let p10 = self.pixel(xb, ya);
+        let p01 = self.pixel(xa, yb);
+        let p11 = self.pixel(xb, yb);
+        let mut color = [0_f32; 4];
+        for (c, value) in color.iter_mut().enumerate() {
+            let top = p00[c] as f32 * (1.0 - fx) + p10[c] as f32 * fx;
+            let bottom = p01[c] as f32 * (1.0 - fx) + p11[c] as f32 * fx;
+            *value = (top * (1.0 - fy) + bottom * fy) / 255.0;
+        }
+        color
+    }
+}
+
+/// Anything an atlas can be built from. DOM sources are drawn with a 2d canvas,
+/// `ImageData` and raw RGBA pixels are copied as is.
+#[derive(Clone)]
+pub enum TextureSource {
+    Bitmap(ImageBitmap),
+    Canvas(HtmlCanvasElement),
+    Image(HtmlImageElement),
+    Data(ImageData),
+    Rgba(RgbaImage),
+}
+
+impl TextureSource {
+    pub fn size(&self) -> (u32, u32) {
+        match self {
+            TextureSource::Bitmap(image) => (image.width(), image.height()),
+            TextureSource::Canvas(canvas) => (canvas.width(), canvas.height()),
+            TextureSource::Image(image) => (image.natural_width(), image.natural_height()),
+            TextureSource::Data(data) => (data.width(), data.height()),
+            TextureSource::Rgba(image) => (image.width, image.height),
+        }
+    }
+
+    /// Pixels of the source if they are available without drawing it on a canvas.
+    pub fn pixels(&self) -> Option<RgbaImage> {
+        match self {
+            TextureSource::Data(data) => Some(RgbaImage { width: data.width(), height: data.height(), pixels: data.data().0 }),
+            TextureSource::Rgba(image) => Some(image.clone()),
+            _ => None,
+        }
+    }
+
+    /// Draws the source region `sx, sy, sw, sh` into `dx, dy, dw, dh` of a 2d canvas context.
+    /// Only DOM sources can be drawn, see `pixels` for the rest.
+    pub fn draw(&self, context: &CanvasRenderingContext2d, src: (f64, f64, f64, f64), dst: (f64, f64, f64, f64)) -> Result<(), JsValue> {
+        let (sx, sy, sw, sh) = src;
+        let (dx, dy, dw, dh) = dst;
+        match self {
+            TextureSource::Bitmap(image) => context.draw_image_with_image_bitmap_and_sw_and_sh_and_dx_and_dy_and_dw_and_dh(
+                image, sx, sy, sw, sh, dx, dy, dw, dh),
+            TextureSource::Canvas(canvas) => context.draw_image_with_html_canvas_element_and_sw_and_sh_and_dx_and_dy_and_dw_and_dh(
+                canvas, sx, sy, sw, sh, dx, dy, dw, dh),
+            TextureSource::Image(image) => context.draw_image_with_html_image_element_and_sw_and_sh_and_dx_and_dy_and_dw_and_dh(
+                image, sx, sy, sw, sh, dx, dy, dw, dh),
+            TextureSource::Data(_) | TextureSource::Rgba(_) => Err(JsValue::from("Pixel sources are not drawn on canvas")),
+        }
+    }
+}
@@ -0,0 +1,97 @@
+use kosygin::background::{Background, ImageFit};
+use kosygin::cpu_renderer::CpuRenderer;
+use kosygin::geom::Point;
+use kosygin::renderer::{Projection, RenderBackend, Sprite};
+use kosygin::texture::{RgbaImage, TextureSource};
+
+fn render(background: Background, sources: &[TextureSource], width: u32, height: u32) -> RgbaImage {
+    let projection = Projection::create(width, height);
+    let mut renderer = CpuRenderer::create(width, height);
+    let atlas = renderer.create_atlas(sources).unwrap();
+    renderer.set_background(background);
+    renderer.render(&projection, &[], &atlas);
+    renderer.frame().clone()
+}
+
+#[test]
+fn clears_to_color_or_transparent() {
+    let frame = render(Background::Color([1.0, 0.5, 0.0, 1.0]), &[], 4, 4);
+    assert_eq!(frame.pixel(2, 2), [255, 128, 0, 255]);
+    let frame = render(Background::Transparent, &[], 4, 4);
+    assert!(frame.pixels.iter().all(|value| *value == 0));
+}
+
+#[test]
+fn only_opaque_colors_and_gradients_are_opaque() {
+    assert!(Background::default().is_opaque());
+    assert!(!Background::Color([0.0, 0.0, 0.0, 0.5]).is_opaque());
+    assert!(!Background::Transparent.is_opaque());
+    assert!(Background::VerticalGradient { top: [0.0, 0.0, 0.2, 1.0], bottom: [1.0; 4] }.is_opaque());
+    assert!(!Background::RadialGradient { inner: [1.0; 4], outer: [0.0; 4] }.is_opaque());
+    assert!(!Background::Image { texture: 0, fit: ImageFit::Cover }.is_opaque());
+}
+
+#[test]
+fn sprites_over_transparent_background_keep_their_alpha() {
+    let projection = Projection::create(8, 8);
+    let mut renderer = CpuRenderer::create(8, 8);
+    let mut image = RgbaImage::create(2, 2);
+    for (x, y) in [(0, 0), (1, 0), (0, 1), (1, 1)].iter() {
+        image.set_pixel(*x, *y, [255, 255, 255, 255]);
+    }
+    let atlas = renderer.create_atlas(&[TextureSource::Rgba(image)]).unwrap();
+    renderer.set_background(Background::Transparent);
+    let sprite = Sprite {
+        position: Point { x: 4.0, y: 4.0 },
+        pivot: Point { x: 4.0, y: 4.0 },
+        width: 8.0,
+        height: 8.0,
+        alpha: 0.5,
+        ..Sprite::default()
+    };
+    renderer.render(&projection, &[sprite], &atlas);
+    // premultiplied in the frame, straight on the canvas
+    assert_eq!(renderer.frame().pixel(4, 4), [128, 128, 128, 128]);
+    assert_eq!(renderer.frame().unpremultiplied().pixel(4, 4), [255, 255, 255, 128]);
+}
+
+#[test]
+fn draws_vertical_and_radial_gradients() {
+    let (black, white) = ([0.0, 0.0, 0.0, 1.0], [1.0, 1.0, 1.0, 1.0]);
+    let frame = render(Background::VerticalGradient { top: black, bottom: white }, &[], 4, 10);
+    assert_eq!(frame.pixel(0, 0), [13, 13, 13, 255]);
+    assert_eq!(frame.pixel(3, 9), [242, 242, 242, 255]);
+    assert_eq!(frame.pixel(0, 5), frame.pixel(3, 5));
+
+    let frame = render(Background::RadialGradient { inner: white, outer: [0.0, 0.0, 1.0, 0.0] }, &[], 10, 10);
+    let (center, corner, edge) = (frame.pixel(5, 5), frame.pixel(0, 0), frame.pixel(0, 5));
+    assert!(center[0] > 200 && center[3] > 200);
+    assert!(corner[3] < 30);
+    assert!(edge[3] < center[3] && edge[3] > corner[3]);
+    assert_eq!(frame.pixel(0, 0), frame.pixel(9, 9));
+}
+
+#[test]
+fn tiles_and_covers_with_atlas_images() {
+    let mut image = RgbaImage::create(2, 2);
+    image.set_pixel(0, 0, [255, 0, 0, 255]);
+    image.set_pixel(1, 0, [0, 255, 0, 255]);
+    image.set_pixel(0, 1, [0, 0, 255, 255]);
+    image.set_pixel(1, 1, [255, 255, 255, 255]);
+    let sources = [TextureSource::Rgba(image)];
+
+    let frame = render(Background::Image { texture: 0, fit: ImageFit::Tile }, &sources, 6, 4);
+    assert_eq!(frame.pixel(0, 0), [255, 0, 0, 255]);
+    assert_eq!(frame.pixel(2, 0), [255, 0, 0, 255]);
+    assert_eq!(frame.pixel(5, 3), [255, 255, 255, 255]);
+
+    // 2x2 scaled to 8x8 covers 8x4, cropping the top and bottom quarter
+    let frame = render(Background::Image { texture: 0, fit: ImageFit::Cover }, &sources, 8, 4);
+    assert_eq!(frame.pixel(0, 0)[3], 255);
+    assert!(frame.pixel(0, 0)[0] > 150 && frame.pixel(0, 0)[2] < 100);
+    assert!(frame.pixel(0, 3)[2] > 150 && frame.pixel(0, 3)[0] < 100);
+
+    // nothing is drawn while the atlas has no such texture
+    let frame = render(Background::Image { texture: 3, fit: ImageFit::Cover }, &sources, 8, 4);
+    assert!(frame.pixels.iter().all(|value| *value == 0));
+}
@@ -0,0 +1,157 @@
+use kosygin::cpu_renderer::CpuRenderer;
+use kosygin::geom::{Mat3, Point};
+use kosygin::buffers::Staging;
+use kosygin::renderer::{batch_sprites, quad_indices, stage_instances, stage_quads, AttributeSpec, BatchOrder, RenderBackend,
+                        Sprite, INSTANCED_VERTEX_SHADER, INSTANCE_ATTRIBUTES, INSTANCE_STRIDE, MAX_BATCH_SPRITES,
+                        QUAD_ATTRIBUTES, QUAD_STRIDE, VERTEX_SHADER};
+use kosygin::texture::{RgbaImage, TextureSource};
+
+#[test]
+fn geometry_stays_correct_past_u16_indices() {
+    let mut renderer = CpuRenderer::create(8, 8);
+    let atlas = renderer.create_atlas(&[TextureSource::Rgba(RgbaImage::create(4, 4))]).unwrap();
+    let count = 2 * MAX_BATCH_SPRITES + 1000;
+    let sprites: Vec<Sprite> = (0..count).map(|i| Sprite {
+        position: Point { x: (i % 1000) as f32, y: (i / 1000) as f32 },
+        pivot: Point { x: 0.5, y: 0.5 },
+        width: 1.0,
+        height: 1.0,
+        ..Sprite::default()
+    }).collect();
+
+    let (order, batches) = batch_sprites(&sprites, &atlas, BatchOrder::Preserve);
+    assert_eq!(batches.len(), 3);
+    assert_eq!(batches.iter().map(|b| b.count).sum::<usize>(), count);
+    assert!(batches.iter().all(|b| b.count <= MAX_BATCH_SPRITES));
+
+    // buffers as the WebGL 1 path uploads them, every batch points the attributes at its first quad
+    let mut vertices = Staging::create();
+    stage_quads(&mut vertices, &sprites, &order, &atlas);
+    let mut indices = Staging::create();
+    indices.push(&quad_indices(batches.iter().map(|b| b.count).max().unwrap()));
+    let indices: Vec<u16> = indices.as_bytes().chunks(2).map(|b| u16::from_le_bytes([b[0], b[1]])).collect();
+    let position = attribute(&QUAD_ATTRIBUTES, "a_position");
+    for batch in batches.iter() {
+        let base = &vertices.as_bytes()[batch.start * 4 * QUAD_STRIDE..];
+        for (triangle, corners) in indices[..batch.count * 6].chunks(3).enumerate() {
+            assert!(corners.iter().all(|&index| (index as usize) < batch.count * 4));
+            let expected = sprites[order[batch.start + triangle / 2]].quad(&atlas);
+            let expected = if triangle % 2 == 0 { [0, 1, 2] } else { [0, 2, 3] }.map(|c| vec![expected[c].position.x, expected[c].position.y]);
+            let actual = [0, 1, 2].map(|c| read(base, QUAD_STRIDE, corners[c] as usize, position));
+            assert_eq!(actual, expected, "sprite {}", batch.start + triangle / 2);
+        }
+    }
+}
+
+#[test]
+#[should_panic]
+fn quad_indices_refuse_to_wrap() {
+    quad_indices(MAX_BATCH_SPRITES + 1);
+}
+
+/// Floats of `attribute` in the vertex or instance `index` of staged data.
+fn read(bytes: &[u8], stride: usize, index: usize, attribute: &AttributeSpec) -> Vec<f32> {
+    let (_, size, _, offset) = *attribute;
+    let start = index * stride + offset as usize;
+    bytes[start..start + size as usize * 4].chunks(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect()
+}
+
+fn attribute(layout: &'static [AttributeSpec], name: &str) -> &'static AttributeSpec {
+    layout.iter().find(|attribute| attribute.0 == name).unwrap()
+}
+
+/// Attributes tile the stride without overlapping and the vertex shader declares every one
+/// with its size.
+fn assert_layout(layout: &[AttributeSpec], stride: usize, shader: &str, qualifier: &str) {
+    let mut end = 0;
+    for &(name, size, _, offset) in layout.iter() {
+        assert_eq!(offset as usize, end, "{}", name);
+        end += size as usize * 4;
+        let glsl_type = ["float", "vec2", "vec3", "vec4"][size as usize - 1];
+        assert!(shader.contains(&format!("{} {} {};", qualifier, glsl_type, name)), "{}", name);
+    }
+    assert_eq!(end, stride);
+}
+
+fn varied_sprites() -> Vec<Sprite> {
+    vec![
+        Sprite {
+            texture: 1,
+            position: Point { x: 20.0, y: 3.0 },
+            pivot: Point { x: 15.0, y: 6.0 },
+            rotation: 0.7,
+            width: 30.0,
+            height: 12.0,
+            alpha: 0.5,
+            ..Sprite::default()
+        },
+        Sprite {
+            texture: 0,
+            position: Point { x: -4.0, y: 9.0 },
+            width: 9.0,
+            height: 5.0,
+            scale: Point { x: 1.5, y: -0.5 },
+            flip_x: true,
+            tint: [1.0, 0.5, 0.25, 1.0],
+            tint_add: [0.0, 0.1, 0.2, 0.0],
+            attributes: [1.0, 2.0, 3.0, 4.0],
+            transform: Mat3::translation(Point { x: 10.0, y: -4.0 }) * Mat3::rotation(0.4) * Mat3::skew(Point { x: 0.3, y: 0.0 }),
+            ..Sprite::default()
+        },
+    ]
+}
+
+#[test]
+fn staged_instances_match_attribute_layout() {
+    assert_layout(&INSTANCE_ATTRIBUTES, INSTANCE_STRIDE, INSTANCED_VERTEX_SHADER, "in");
+    let mut renderer = CpuRenderer::create(8, 8);
+    let atlas = renderer.create_atlas(&[TextureSource::Rgba(RgbaImage::create(4, 4)),
+                                        TextureSource::Rgba(RgbaImage::create(6, 3))]).unwrap();
+    let sprites = varied_sprites();
+    let mut staging = Staging::create();
+    stage_instances(&mut staging, &sprites, &[1, 0], &atlas);
+    let bytes = staging.as_bytes();
+    assert_eq!(bytes.len(), 2 * INSTANCE_STRIDE);
+
+    for (index, sprite) in [&sprites[1], &sprites[0]].iter().enumerate() {
+        let read = |name: &str| read(bytes, INSTANCE_STRIDE, index, attribute(&INSTANCE_ATTRIBUTES, name));
+        let m = &sprite.transform.m;
+        assert_eq!(read("a_position"), vec![sprite.position.x, sprite.position.y]);
+        assert_eq!(read("a_pivot"), vec![sprite.pivot.x, sprite.pivot.y]);
+        assert_eq!(read("a_size"), vec![sprite.width, sprite.height]);
+        assert_eq!(read("a_rotation"), vec![sprite.rotation]);
+        assert_eq!(read("a_alpha"), vec![sprite.alpha]);
+        assert_eq!(read("a_texRect"), sprite.tex_rect(&atlas).to_vec());
+        assert_eq!(read("a_transformX"), vec![m[0], m[3], m[6]]);
+        assert_eq!(read("a_transformY"), vec![m[1], m[4], m[7]]);
+        assert_eq!(read("a_scale"), vec![sprite.scale.x, sprite.scale.y]);
+        assert_eq!(read("a_tint"), sprite.tint.to_vec());
+        assert_eq!(read("a_tintAdd"), sprite.tint_add.to_vec());
+        assert_eq!(read("a_attributes"), sprite.attributes.to_vec());
+    }
+}
+
+#[test]
+fn staged_quads_match_attribute_layout() {
+    assert_layout(&QUAD_ATTRIBUTES, QUAD_STRIDE, VERTEX_SHADER, "attribute");
+    let mut renderer = CpuRenderer::create(8, 8);
+    let atlas = renderer.create_atlas(&[TextureSource::Rgba(RgbaImage::create(4, 4)),
+                                        TextureSource::Rgba(RgbaImage::create(6, 3))]).unwrap();
+    let sprites = varied_sprites();
+    let mut staging = Staging::create();
+    stage_quads(&mut staging, &sprites, &[0, 1], &atlas);
+    let bytes = staging.as_bytes();
+    assert_eq!(bytes.len(), 8 * QUAD_STRIDE);
+
+    for (index, vertex) in sprites.iter().flat_map(|sprite| sprite.quad(&atlas)).enumerate() {
+        let read = |name: &str| read(bytes, QUAD_STRIDE, index, attribute(&QUAD_ATTRIBUTES, name));
+        assert_eq!(read("a_position"), vec![vertex.position.x, vertex.position.y]);
+        assert_eq!(read("a_texCoord"), vec![vertex.tex_coord.x, vertex.tex_coord.y]);
+        assert_eq!(read("a_alpha"), vec![vertex.alpha]);
+        assert_eq!(read("a_tint"), vertex.tint.to_vec());
+        assert_eq!(read("a_tintAdd"), vertex.tint_add.to_vec());
+        assert_eq!(read("a_attributes"), vertex.attributes.to_vec());
+    }
+}
@@ -0,0 +1,83 @@
+use kosygin::buffers::{BufferState, BufferUpdate, Staging};
+
+fn stage(staging: &mut Staging<f32>, data: &[f32]) {
+    staging.clear();
+    staging.push(data);
+}
+
+#[test]
+fn staging_tracks_changed_bytes() {
+    let mut staging = Staging::create();
+    let data: Vec<f32> = (0..10).map(|i| i as f32).collect();
+    stage(&mut staging, &data);
+    assert_eq!(staging.take_dirty(), Some(0..40));
+    assert_eq!(staging.take_dirty(), None);
+    stage(&mut staging, &data);
+    assert_eq!(staging.take_dirty(), None);
+
+    let mut moved = data.clone();
+    moved[2] = -1.0;
+    moved[5] = -1.0;
+    stage(&mut staging, &moved);
+    assert_eq!(staging.take_dirty(), Some(8..24));
+
+    // fewer elements are no change, growing back into the old bytes compares against them
+    stage(&mut staging, &moved[..4]);
+    assert_eq!(staging.take_dirty(), None);
+    assert_eq!(staging.as_bytes().len(), 16);
+    stage(&mut staging, &moved);
+    assert_eq!(staging.take_dirty(), None);
+    let mut longer = moved.clone();
+    longer.push(10.0);
+    stage(&mut staging, &longer);
+    assert_eq!(staging.take_dirty(), Some(40..44));
+}
+
+#[test]
+fn buffer_grows_geometrically_and_updates_in_place() {
+    let mut state = BufferState::create();
+    let mut staging = Staging::create();
+    let data: Vec<f32> = (0..100).map(|i| i as f32).collect();
+    stage(&mut staging, &data);
+    assert_eq!(state.update(&mut staging), BufferUpdate::Reallocate { capacity: 1024 });
+    stage(&mut staging, &data);
+    assert_eq!(state.update(&mut staging), BufferUpdate::None);
+
+    let mut moved = data.clone();
+    moved[10] = -1.0;
+    moved[20] = -1.0;
+    stage(&mut staging, &moved);
+    assert_eq!(state.update(&mut staging), BufferUpdate::Update { range: 40..84 });
+
+    let more: Vec<f32> = (0..600).map(|i| i as f32).collect();
+    stage(&mut staging, &more);
+    assert_eq!(state.update(&mut staging), BufferUpdate::Reallocate { capacity: 2400 });
+    stage(&mut staging, &more[..200]);
+    assert_eq!(state.update(&mut staging), BufferUpdate::None);
+    stage(&mut staging, &more[..300]);
+    assert_eq!(state.update(&mut staging), BufferUpdate::None);
+    assert_eq!(state.capacity(), 2400);
+
+    // reallocated storage holds only what was staged then, anything past it is uploaded again
+    state.reset();
+    stage(&mut staging, &more[..10]);
+    assert_eq!(state.update(&mut staging), BufferUpdate::Reallocate { capacity: 1024 });
+    stage(&mut staging, &more[..200]);
+    assert_eq!(state.update(&mut staging), BufferUpdate::Update { range: 40..800 });
+}
+
+#[test]
+fn staging_keeps_little_endian_bytes() {
+    let mut staging = Staging::create();
+    staging.push(&[1.0_f32, -2.5]);
+    assert_eq!(staging.len(), 2);
+    let mut expected = 1.0_f32.to_le_bytes().to_vec();
+    expected.extend_from_slice(&(-2.5_f32).to_le_bytes());
+    assert_eq!(staging.as_bytes(), expected.as_slice());
+
+    staging.clear();
+    assert!(staging.is_empty());
+    let mut indices = Staging::create();
+    indices.push(&[1_u16, 258]);
+    assert_eq!(indices.as_bytes(), &[1, 0, 2, 1]);
+}
@@ -0,0 +1,108 @@
+use core::f32::consts::PI;
+
+use kosygin::camera::{client_to_screen, Camera, MAX_ZOOM, MIN_ZOOM};
+use kosygin::geom::{Mat3, Point, Rect};
+use kosygin::renderer::Projection;
+
+fn close(a: Point, b: Point) -> bool {
+    (a.x - b.x).abs() < 1e-3 && (a.y - b.y).abs() < 1e-3
+}
+
+#[test]
+fn default_camera_shows_world_as_is() {
+    let camera = Camera::create(200, 100);
+    assert_eq!(camera.view_matrix(), Mat3::identity());
+    let mut projection = Projection::create(200, 100);
+    let before = *projection.matrix();
+    projection.set_camera(&camera);
+    assert_eq!(*projection.matrix(), before);
+}
+
+#[test]
+fn zoom_and_rotation_keep_point_under_cursor() {
+    let mut camera = Camera::create(200, 100);
+    let cursor = Point { x: 150.0, y: 20.0 };
+    let anchor = camera.screen_to_world(cursor);
+    camera.zoom_at(cursor, 2.5);
+    assert!(close(camera.screen_to_world(cursor), anchor));
+    camera.rotate_at(cursor, PI / 6.0);
+    assert!(close(camera.screen_to_world(cursor), anchor));
+    assert!(close(camera.world_to_screen(anchor), cursor));
+    // screen pixels shrink with zoom
+    let other = camera.screen_to_world(Point { x: 155.0, y: 20.0 });
+    assert!(((other - anchor).length() - 2.0).abs() < 1e-3);
+}
+
+#[test]
+fn zoom_is_clamped() {
+    let mut camera = Camera::create(200, 100);
+    let cursor = Point { x: 150.0, y: 20.0 };
+    let anchor = camera.screen_to_world(cursor);
+    for _ in 0..100 {
+        camera.zoom_at(cursor, 2.0);
+    }
+    assert_eq!(camera.zoom, MAX_ZOOM);
+    assert!(close(camera.screen_to_world(cursor), anchor));
+    camera.zoom_at(cursor, 1e-9);
+    assert_eq!(camera.zoom, MIN_ZOOM);
+    assert!(close(camera.screen_to_world(cursor), anchor));
+    camera.zoom_at(cursor, 0.0);
+    camera.zoom_at(cursor, f32::NAN);
+    assert_eq!(camera.zoom, MIN_ZOOM);
+}
+
+#[test]
+fn visible_rect_follows_camera_and_is_empty_for_singular_views() {
+    let mut camera = Camera::create(200, 100);
+    let mut projection = Projection::create(200, 100);
+    camera.zoom_at(Point { x: 0.0, y: 0.0 }, 2.0);
+    projection.set_camera(&camera);
+    let visible = projection.visible_rect();
+    assert!(close(Point { x: visible.x, y: visible.y }, Point { x: 0.0, y: 0.0 }));
+    assert!(close(Point { x: visible.width, y: visible.height }, Point { x: 100.0, y: 50.0 }));
+
+    camera.zoom = 0.0;
+    projection.set_camera(&camera);
+    let visible = projection.visible_rect();
+    assert_eq!((visible.width, visible.height), (0.0, 0.0));
+    assert!(!visible.intersects(&Rect { x: -1e6, y: -1e6, width: 2e6, height: 2e6 }));
+}
+
+#[test]
+fn pointer_in_css_pixels_maps_to_device_pixels() {
+    // 200x100 CSS pixels at 2 device pixels each, 10 pixels below the top of the page
+    let mut camera = Camera::create(400, 200);
+    let origin = Point { x: 0.0, y: 10.0 };
+    let centre = client_to_screen(Point { x: 100.0, y: 60.0 }, origin, 2.0);
+    assert!(close(centre, Point { x: 200.0, y: 100.0 }));
+    assert!(close(camera.screen_to_world(centre), camera.position));
+
+    let pointer = client_to_screen(Point { x: 150.0, y: 30.0 }, origin, 2.0);
+    assert!(close(pointer, Point { x: 300.0, y: 40.0 }));
+    let anchor = camera.screen_to_world(pointer);
+    camera.zoom_at(pointer, 1.5);
+    assert!(close(camera.screen_to_world(pointer), anchor));
+    assert!(close(camera.world_to_screen(anchor), pointer));
+}
+
+#[test]
+fn pan_drags_world_with_pointer() {
+    let mut camera = Camera::create(200, 100);
+    camera.zoom_at(Point { x: 100.0, y: 50.0 }, 2.0);
+    camera.rotate_at(Point { x: 100.0, y: 50.0 }, PI * 0.5);
+    let grabbed = camera.screen_to_world(Point { x: 40.0, y: 40.0 });
+    camera.pan(Point { x: 30.0, y: -10.0 });
+    assert!(close(camera.world_to_screen(grabbed), Point { x: 70.0, y: 30.0 }));
+}
+
+#[test]
+fn resize_keeps_top_left_corner() {
+    let mut camera = Camera::create(200, 100);
+    camera.zoom_at(Point { x: 10.0, y: 10.0 }, 3.0);
+    let corner = camera.screen_to_world(Point { x: 0.0, y: 0.0 });
+    camera.resize(400, 300);
+    assert!(close(camera.screen_to_world(Point { x: 0.0, y: 0.0 }), corner));
+    let mut fresh = Camera::create(200, 100);
+    fresh.resize(400, 300);
+    assert_eq!(fresh.view_matrix(), Mat3::identity());
+}
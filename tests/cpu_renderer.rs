@@ -0,0 +1,295 @@
+use core::f32::consts::PI;
+
+use kosygin::camera::Camera;
+use kosygin::cpu_renderer::CpuRenderer;
+use kosygin::texture::{RgbaImage, TextureSource};
+use kosygin::geom::Point;
+use kosygin::layer::Layer;
+use kosygin::material::DEFAULT_MATERIAL;
+use kosygin::packer::AtlasConfig;
+use kosygin::postprocess::PostEffect;
+use kosygin::renderer::{batch_sprites, Batch, BatchOrder, BlendMode, Projection, RenderBackend, Sprite};
+
+fn solid(width: u32, height: u32, color: [u8; 4]) -> RgbaImage {
+    let mut image = RgbaImage::create(width, height);
+    for y in 0..height {
+        for x in 0..width {
+            image.set_pixel(x, y, color);
+        }
+    }
+    image
+}
+
+fn sprite(x: f32, y: f32, size: f32, rotation: f32, alpha: f32) -> Sprite {
+    Sprite {
+        texture: 0,
+        position: Point { x, y },
+        pivot: Point { x: size * 0.5, y: size * 0.5 },
+        rotation,
+        width: size,
+        height: size,
+        alpha,
+        ..Sprite::default()
+    }
+}
+
+#[test]
+fn renders_opaque_sprite_over_black() {
+    let projection = Projection::create(32, 32);
+    let mut renderer = CpuRenderer::create(32, 32);
+    let atlas = renderer.create_atlas(&[TextureSource::Rgba(solid(4, 4, [255, 0, 0, 255]))]).unwrap();
+    renderer.render(&projection, &[sprite(16.0, 16.0, 10.0, 0.0, 1.0)], &atlas);
+    let frame = renderer.frame();
+    assert_eq!(frame.pixel(16, 16), [255, 0, 0, 255]);
+    assert_eq!(frame.pixel(11, 11), [255, 0, 0, 255]);
+    assert_eq!(frame.pixel(20, 20), [255, 0, 0, 255]);
+    assert_eq!(frame.pixel(21, 21), [0, 0, 0, 255]);
+    assert_eq!(frame.pixel(10, 16), [0, 0, 0, 255]);
+    assert_eq!(frame.pixel(0, 0), [0, 0, 0, 255]);
+}
+
+#[test]
+fn shared_quad_edge_is_drawn_once() {
+    let projection = Projection::create(16, 16);
+    let mut renderer = CpuRenderer::create(16, 16);
+    let atlas = renderer.create_atlas(&[TextureSource::Rgba(solid(2, 2, [255, 255, 255, 255]))]).unwrap();
+    renderer.render(&projection, &[sprite(8.0, 8.0, 16.0, 0.0, 0.5)], &atlas);
+    let frame = renderer.frame();
+    let first = frame.pixel(0, 0);
+    for y in 0..16 {
+        for x in 0..16 {
+            assert_eq!(frame.pixel(x, y), first, "pixel {} {}", x, y);
+        }
+    }
+}
+
+#[test]
+fn rotated_quad_diagonal_has_no_gaps_or_overlaps() {
+    let projection = Projection::create(32, 32);
+    let mut renderer = CpuRenderer::create(32, 32);
+    let atlas = renderer.create_atlas(&[TextureSource::Rgba(solid(2, 2, [255, 255, 255, 255]))]).unwrap();
+    for step in 0..48 {
+        let rotation = (step / 12) as f32 * PI * 0.5;
+        let (cx, cy) = (16.0 + (step % 4) as f32 * 0.25, 16.0 + (step % 3) as f32 * 0.5);
+        renderer.render(&projection, &[sprite(cx, cy, 20.0, rotation, 0.5)], &atlas);
+        let frame = renderer.frame();
+        for y in 0..32 {
+            for x in 0..32 {
+                let p = Point { x: x as f32 + 0.5, y: y as f32 + 0.5 };
+                let local = (p - Point { x: cx, y: cy }).rotate(-rotation) + Point { x: 10.0, y: 10.0 };
+                let margin = local.x.min(local.y).min(20.0 - local.x).min(20.0 - local.y);
+                if margin.abs() < 0.01 {
+                    continue;
+                }
+                // drawn exactly once inside, a second draw would give 191
+                let red = frame.pixel(x, y)[0];
+                let covered = if margin > 0.0 { red == 127 || red == 128 } else { red == 0 };
+                assert!(covered, "pixel {} {} is {} at rotation {}", x, y, red, rotation);
+            }
+        }
+    }
+}
+
+#[test]
+fn blends_with_sprite_alpha() {
+    let projection = Projection::create(8, 8);
+    let mut renderer = CpuRenderer::create(8, 8);
+    let atlas = renderer.create_atlas(&[TextureSource::Rgba(solid(2, 2, [200, 100, 0, 255]))]).unwrap();
+    renderer.render(&projection, &[sprite(4.0, 4.0, 8.0, 0.0, 0.5)], &atlas);
+    assert_eq!(renderer.frame().pixel(4, 4), [100, 50, 0, 255]);
+}
+
+#[test]
+fn rotates_sprite_around_pivot() {
+    let projection = Projection::create(40, 40);
+    let mut renderer = CpuRenderer::create(40, 40);
+    let atlas = renderer.create_atlas(&[TextureSource::Rgba(solid(2, 2, [0, 255, 0, 255]))]).unwrap();
+    let mut long = sprite(20.0, 20.0, 30.0, PI * 0.5, 1.0);
+    long.height = 4.0;
+    long.pivot = Point { x: 15.0, y: 2.0 };
+    renderer.render(&projection, &[long], &atlas);
+    let frame = renderer.frame();
+    assert_eq!(frame.pixel(20, 7), [0, 255, 0, 255]);
+    assert_eq!(frame.pixel(20, 32), [0, 255, 0, 255]);
+    assert_eq!(frame.pixel(7, 20), [0, 0, 0, 255]);
+    assert_eq!(frame.pixel(32, 20), [0, 0, 0, 255]);
+}
+
+#[test]
+fn renders_sprites_from_several_atlas_pages() {
+    let projection = Projection::create(32, 16);
+    let mut renderer = CpuRenderer::create(32, 16);
+    renderer.set_atlas_config(AtlasConfig { max_size: 16, padding: 2, extrusion: 1, max_pages: 4 });
+    let atlas = renderer.create_atlas(&[TextureSource::Rgba(solid(12, 12, [255, 0, 0, 255])), TextureSource::Rgba(solid(12, 12, [0, 0, 255, 255]))]).unwrap();
+    assert_eq!(atlas.pages().len(), 2);
+    let mut blue = sprite(24.0, 8.0, 16.0, 0.0, 1.0);
+    blue.texture = 1;
+    renderer.render(&projection, &[sprite(8.0, 8.0, 16.0, 0.0, 1.0), blue], &atlas);
+    assert_eq!(renderer.frame().pixel(8, 8), [255, 0, 0, 255]);
+    assert_eq!(renderer.frame().pixel(24, 8), [0, 0, 255, 255]);
+    assert_eq!(renderer.frame_stats().draw_calls, 2);
+}
+
+#[test]
+fn batches_sprites_by_page() {
+    let mut renderer = CpuRenderer::create(8, 8);
+    renderer.set_atlas_config(AtlasConfig { max_size: 16, padding: 2, extrusion: 1, max_pages: 4 });
+    let atlas = renderer.create_atlas(&[TextureSource::Rgba(solid(12, 12, [255, 0, 0, 255])), TextureSource::Rgba(solid(12, 12, [0, 0, 255, 255]))]).unwrap();
+    let page_of = |texture: usize| atlas.item(texture).page;
+    let textures = [0, 1, 1, 0, 1];
+    let sprites: Vec<Sprite> = textures.iter().map(|t| Sprite { texture: *t, ..sprite(0.0, 0.0, 1.0, 0.0, 1.0) }).collect();
+
+    let (order, batches) = batch_sprites(&sprites, &atlas, BatchOrder::Preserve);
+    assert_eq!(order, vec![0, 1, 2, 3, 4]);
+    assert_eq!(batches, vec![
+        Batch { layer: Layer::Flakes, page: page_of(0), material: DEFAULT_MATERIAL, blend: BlendMode::Normal, start: 0, count: 1 },
+        Batch { layer: Layer::Flakes, page: page_of(1), material: DEFAULT_MATERIAL, blend: BlendMode::Normal, start: 1, count: 2 },
+        Batch { layer: Layer::Flakes, page: page_of(0), material: DEFAULT_MATERIAL, blend: BlendMode::Normal, start: 3, count: 1 },
+        Batch { layer: Layer::Flakes, page: page_of(1), material: DEFAULT_MATERIAL, blend: BlendMode::Normal, start: 4, count: 1 },
+    ]);
+
+    let (order, batches) = batch_sprites(&sprites, &atlas, BatchOrder::ByPage);
+    assert_eq!(batches.len(), 2);
+    for batch in batches.iter() {
+        let batch_sprites = &order[batch.start..batch.start + batch.count];
+        assert!(batch_sprites.iter().all(|i| page_of(sprites[*i].texture) == batch.page));
+        assert!(batch_sprites.windows(2).all(|w| w[0] < w[1]), "order within a page is kept");
+    }
+}
+
+#[test]
+fn culls_sprites_outside_viewport() {
+    let mut projection = Projection::create(40, 40);
+    let mut renderer = CpuRenderer::create(40, 40);
+    let atlas = renderer.create_atlas(&[TextureSource::Rgba(solid(2, 2, [255, 255, 255, 255]))]).unwrap();
+    // the rotated one reaches into the canvas only by its corner
+    let sprites = [sprite(20.0, 20.0, 10.0, 0.0, 1.0), sprite(-10.0, 20.0, 10.0, 0.0, 1.0),
+                   sprite(46.0, 20.0, 10.0, PI * 0.25, 1.0), sprite(20.0, 60.0, 10.0, 0.0, 1.0)];
+    renderer.render(&projection, &sprites, &atlas);
+    let stats = renderer.frame_stats();
+    assert_eq!((stats.drawn_sprites, stats.culled_sprites), (2, 2));
+    assert_eq!(renderer.frame().pixel(39, 20), [255, 255, 255, 255]);
+
+    let mut camera = Camera::create(40, 40);
+    camera.pan(Point { x: 0.0, y: -40.0 });
+    projection.set_camera(&camera);
+    renderer.render(&projection, &sprites, &atlas);
+    let stats = renderer.frame_stats();
+    assert_eq!((stats.drawn_sprites, stats.culled_sprites), (1, 3));
+    assert_eq!(renderer.frame().pixel(20, 20), [255, 255, 255, 255]);
+}
+
+#[test]
+fn draws_layers_in_order_and_hides_toggled_ones() {
+    let projection = Projection::create(20, 20);
+    let mut renderer = CpuRenderer::create(20, 20);
+    let atlas = renderer.create_atlas(&[TextureSource::Rgba(solid(2, 2, [255, 0, 0, 255])),
+                                        TextureSource::Rgba(solid(2, 2, [0, 0, 255, 255])),
+                                        TextureSource::Rgba(solid(2, 2, [0, 255, 0, 255]))]).unwrap();
+    let banner = Sprite { texture: 0, layer: Layer::Overlay, ..sprite(10.0, 10.0, 8.0, 0.0, 1.0) };
+    let near = Sprite { texture: 1, z: 1, ..sprite(10.0, 10.0, 12.0, 0.0, 1.0) };
+    let far = Sprite { texture: 2, ..sprite(10.0, 10.0, 16.0, 0.0, 1.0) };
+    let sprites = [banner, near, far];
+
+    let (order, _) = batch_sprites(&sprites, &atlas, BatchOrder::ByPage);
+    assert_eq!(order, vec![2, 1, 0]);
+    renderer.render(&projection, &sprites, &atlas);
+    assert_eq!(renderer.frame().pixel(10, 10), [255, 0, 0, 255]);
+    assert_eq!(renderer.frame().pixel(5, 10), [0, 0, 255, 255]);
+    assert_eq!(renderer.frame().pixel(3, 10), [0, 255, 0, 255]);
+
+    renderer.set_layer_visible(Layer::Overlay, false);
+    renderer.render(&projection, &sprites, &atlas);
+    assert_eq!(renderer.frame().pixel(10, 10), [0, 0, 255, 255]);
+    assert_eq!(renderer.frame_stats().drawn_sprites, 2);
+}
+
+#[test]
+fn tints_scales_and_flips_sprites() {
+    let mut image = solid(4, 4, [255, 255, 255, 255]);
+    for y in 0..4 {
+        for x in 0..2 {
+            image.set_pixel(x, y, [0, 0, 0, 255]);
+        }
+    }
+    let projection = Projection::create(40, 20);
+    let mut renderer = CpuRenderer::create(40, 20);
+    let atlas = renderer.create_atlas(&[TextureSource::Rgba(image)]).unwrap();
+    let plain = sprite(10.0, 10.0, 8.0, 0.0, 1.0);
+    let tinted = Sprite { tint: [1.0, 0.5, 0.0, 1.0], tint_add: [0.0, 0.0, 0.4, 0.0], ..sprite(30.0, 10.0, 8.0, 0.0, 1.0) };
+    renderer.render(&projection, &[plain.clone(), tinted], &atlas);
+    let frame = renderer.frame();
+    assert_eq!(frame.pixel(7, 10), [0, 0, 0, 255]);
+    assert_eq!(frame.pixel(12, 10), [255, 255, 255, 255]);
+    assert_eq!(frame.pixel(27, 10), [0, 0, 102, 255]);
+    assert_eq!(frame.pixel(32, 10), [255, 128, 102, 255]);
+
+    let flipped = Sprite { flip_x: true, scale: Point { x: 2.0, y: 0.5 }, ..plain };
+    renderer.render(&projection, std::slice::from_ref(&flipped), &atlas);
+    let frame = renderer.frame();
+    // 16x4 around the pivot, white half on the left
+    assert_eq!(frame.pixel(3, 10), [255, 255, 255, 255]);
+    assert_eq!(frame.pixel(16, 10), [0, 0, 0, 255]);
+    assert_eq!(frame.pixel(10, 7), [0, 0, 0, 255]);
+    assert_eq!(flipped.bounds().width, 16.0);
+}
+
+#[test]
+fn blends_sprites_by_mode() {
+    let projection = Projection::create(40, 10);
+    let mut renderer = CpuRenderer::create(40, 10);
+    let atlas = renderer.create_atlas(&[TextureSource::Rgba(solid(2, 2, [128, 128, 128, 255])),
+                                        TextureSource::Rgba(solid(2, 2, [100, 50, 200, 255]))]).unwrap();
+    let mut sprites = vec![sprite(20.0, 5.0, 40.0, 0.0, 1.0)];
+    for (i, blend) in [BlendMode::Normal, BlendMode::Additive, BlendMode::Multiply, BlendMode::Screen].iter().enumerate() {
+        sprites.push(Sprite { texture: 1, blend: *blend, ..sprite(5.0 + 10.0 * i as f32, 5.0, 10.0, 0.0, 1.0) });
+    }
+    renderer.render(&projection, &sprites, &atlas);
+    let frame = renderer.frame();
+    assert_eq!(frame.pixel(5, 5), [100, 50, 200, 255]);
+    assert_eq!(frame.pixel(15, 5), [228, 178, 255, 255]);
+    assert_eq!(frame.pixel(25, 5), [50, 25, 100, 255]);
+    assert_eq!(frame.pixel(35, 5), [178, 153, 228, 255]);
+    // the backdrop and the normal sprite share a batch
+    assert_eq!(renderer.frame_stats().draw_calls, 4);
+}
+
+#[test]
+fn filters_transparent_pixels_without_dark_fringes() {
+    // white next to transparent black, filtered straight the white would also fade to black
+    let mut image = RgbaImage::create(2, 1);
+    image.set_pixel(0, 0, [255, 255, 255, 255]);
+    let projection = Projection::create(8, 8);
+    let mut renderer = CpuRenderer::create(8, 8);
+    renderer.set_atlas_config(AtlasConfig { max_size: 16, padding: 0, extrusion: 0, max_pages: 1 });
+    let atlas = renderer.create_atlas(&[TextureSource::Rgba(image)]).unwrap();
+    let stretched = Sprite { pivot: Point { x: 0.0, y: 0.0 }, ..sprite(0.0, 0.0, 8.0, 0.0, 1.0) };
+    renderer.render(&projection, &[stretched], &atlas);
+    // 3/8 of the white texel
+    assert_eq!(renderer.frame().pixel(4, 4), [96, 96, 96, 255]);
+}
+
+#[test]
+fn runs_layer_and_frame_effects() {
+    let projection = Projection::create(20, 10);
+    let mut renderer = CpuRenderer::create(20, 10);
+    let atlas = renderer.create_atlas(&[TextureSource::Rgba(solid(2, 2, [255, 255, 255, 255]))]).unwrap();
+    let far = Sprite { layer: Layer::Background, ..sprite(5.0, 5.0, 4.0, 0.0, 1.0) };
+    let near = sprite(15.0, 5.0, 4.0, 0.0, 1.0);
+    renderer.set_layer_effects(Layer::Background, vec![PostEffect::Blur { radius: 3.0 }]);
+    renderer.render(&projection, &[far.clone(), near.clone()], &atlas);
+    let frame = renderer.frame();
+    // the blurred layer is composed over black, the other one stays sharp
+    assert!(frame.pixel(5, 5)[0] < 255 && frame.pixel(2, 5)[0] > 0);
+    assert_eq!(frame.pixel(5, 5)[3], 255);
+    assert_eq!(frame.pixel(15, 5), [255, 255, 255, 255]);
+    assert_eq!(frame.pixel(12, 5), [0, 0, 0, 255]);
+    assert_eq!(renderer.frame_stats().post_passes, 2);
+
+    renderer.set_layer_effects(Layer::Background, Vec::new());
+    renderer.set_post_effects(vec![PostEffect::ColorGrade { brightness: 0.0, contrast: 1.0, saturation: 1.0, tint: [1.0, 0.0, 0.0] }]);
+    renderer.render(&projection, &[far, near], &atlas);
+    assert_eq!(renderer.frame().pixel(5, 5), [255, 0, 0, 255]);
+    assert_eq!(renderer.frame().pixel(15, 5), [255, 0, 0, 255]);
+    assert_eq!(renderer.frame_stats().post_passes, 1);
+}
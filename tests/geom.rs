@@ -0,0 +1,65 @@
+use core::f32::consts::PI;
+
+use kosygin::geom::{Mat3, Point, Rect, Vec2};
+
+fn close(a: Point, b: Point) -> bool {
+    (a.x - b.x).abs() < 1e-4 && (a.y - b.y).abs() < 1e-4
+}
+
+#[test]
+fn vector_operations() {
+    let a = Vec2 { x: 3.0, y: 4.0 };
+    let b = Vec2 { x: -2.0, y: 1.0 };
+    assert_eq!(a.dot(b), -2.0);
+    assert_eq!(a.cross(b), 11.0);
+    assert_eq!(a.length(), 5.0);
+    assert!(close(a.normalize(), Point { x: 0.6, y: 0.8 }));
+    assert_eq!(Point { x: 0.0, y: 0.0 }.normalize(), Point { x: 0.0, y: 0.0 });
+    assert_eq!(a.lerp(b, 0.5), Point { x: 0.5, y: 2.5 });
+    assert_eq!(-a / 2.0, Point { x: -1.5, y: -2.0 });
+
+    let mut c = a;
+    c += b;
+    c *= 2.0;
+    c -= a;
+    c /= 2.0;
+    assert_eq!(c, Point { x: -0.5, y: 3.0 });
+}
+
+#[test]
+fn rotation_by_sine_and_cosine_matches_angle() {
+    let p = Point { x: 2.0, y: -1.0 };
+    let (sin, cos) = 0.7_f32.sin_cos();
+    assert_eq!(p.rotate_sin_cos(sin, cos), p.rotate(0.7));
+    assert!(close(p.rotate(PI * 0.5), Point { x: 1.0, y: 2.0 }));
+}
+
+#[test]
+fn inverse_undoes_transform() {
+    let m = Mat3::translation(Point { x: 10.0, y: -5.0 }) * Mat3::rotation(PI / 3.0)
+        * Mat3::scale(Point { x: 2.0, y: 0.5 });
+    let inverse = m.invert().unwrap();
+    let p = Point { x: 7.0, y: 3.0 };
+    assert!(close(inverse.transform_point(m.transform_point(p)), p));
+    let identity = m * inverse;
+    for (a, b) in identity.m.iter().zip(Mat3::identity().m.iter()) {
+        assert!((a - b).abs() < 1e-5);
+    }
+    assert!(close(m.transform_vector(Point { x: 1.0, y: 0.0 }), Point { x: 1.0, y: 3_f32.sqrt() }));
+    assert!(Mat3::scale(Point { x: 0.0, y: 1.0 }).invert().is_none());
+}
+
+#[test]
+fn rect_intersection_and_containment() {
+    let a = Rect { x: 0.0, y: 0.0, width: 10.0, height: 10.0 };
+    let b = Rect { x: 5.0, y: -5.0, width: 10.0, height: 10.0 };
+    assert_eq!(a.intersection(&b), Some(Rect { x: 5.0, y: 0.0, width: 5.0, height: 5.0 }));
+    assert!(!a.intersects(&Rect { x: 10.0, y: 0.0, width: 5.0, height: 5.0 }));
+    assert!(a.contains(Point { x: 0.0, y: 9.5 }));
+    assert!(!a.contains(Point { x: 10.0, y: 5.0 }));
+    assert!(a.contains_rect(&Rect { x: 2.0, y: 2.0, width: 8.0, height: 3.0 }));
+    assert!(!a.contains_rect(&b));
+    let points = [Point { x: 3.0, y: -1.0 }, Point { x: -2.0, y: 4.0 }, Point { x: 1.0, y: 1.0 }];
+    assert_eq!(Rect::bounding(&points), Some(Rect { x: -2.0, y: -1.0, width: 5.0, height: 5.0 }));
+    assert_eq!(Rect::bounding(&[]), None);
+}
@@ -0,0 +1,143 @@
+//! Golden-image regression tests: fixed seeded scenes are rendered with `CpuRenderer`
+//! and compared with reference PNGs in `tests/golden`.
+//!
+//! Run with `UPDATE_GOLDEN=1 cargo test --test golden` to regenerate the references after
+//! an intended rendering change. On mismatch the rendered frame and a diff image
+//! (mismatching pixels in red) are written to `target/golden`.
+
+use std::env;
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+use kosygin::cpu_renderer::CpuRenderer;
+use kosygin::texture::{RgbaImage, TextureSource};
+use kosygin::geom::Point;
+use kosygin::random::XorShift;
+use kosygin::renderer::{Projection, RenderBackend, Sprite};
+use kosygin::simulation::Simulation;
+
+/// Maximum per-channel difference still considered equal.
+const TOLERANCE: u8 = 2;
+
+const SNOWFLAKES: [&str; 6] = ["snowflake0.png", "snowflake1.png", "snowflake2.png",
+    "snowflake3.png", "snowflake4.png", "snowflake5.png"];
+
+fn root() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+}
+
+fn load_png(path: &Path) -> RgbaImage {
+    let decoder = png::Decoder::new(File::open(path).unwrap_or_else(|e| panic!("{}: {}", path.display(), e)));
+    let mut reader = decoder.read_info().unwrap();
+    let mut buffer = vec![0_u8; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer).unwrap();
+    assert_eq!(info.bit_depth, png::BitDepth::Eight, "{}", path.display());
+    let pixels = match info.color_type {
+        png::ColorType::Rgba => buffer[..info.buffer_size()].to_vec(),
+        png::ColorType::Rgb => buffer[..info.buffer_size()].chunks_exact(3)
+            .flat_map(|p| vec![p[0], p[1], p[2], 255])
+            .collect(),
+        other => panic!("{}: unsupported color type {:?}", path.display(), other),
+    };
+    RgbaImage { width: info.width, height: info.height, pixels }
+}
+
+fn save_png(path: &Path, image: &RgbaImage) {
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    let file = File::create(path).unwrap();
+    let mut encoder = png::Encoder::new(BufWriter::new(file), image.width, image.height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header().unwrap().write_image_data(&image.pixels).unwrap();
+}
+
+/// Returns the number of pixels differing by more than `tolerance` in any channel
+/// and an image with those pixels marked red over a dimmed copy of `expected`.
+fn diff(actual: &RgbaImage, expected: &RgbaImage, tolerance: u8) -> (usize, RgbaImage) {
+    let mut image = RgbaImage::create(expected.width, expected.height);
+    let mut mismatches = 0;
+    for y in 0..expected.height {
+        for x in 0..expected.width {
+            let a = actual.pixel(x, y);
+            let e = expected.pixel(x, y);
+            let differs = a.iter().zip(e.iter()).any(|(a, e)| (*a as i16 - *e as i16).abs() > tolerance as i16);
+            if differs {
+                mismatches += 1;
+                image.set_pixel(x, y, [255, 0, 0, 255]);
+            } else {
+                image.set_pixel(x, y, [e[0] / 4, e[1] / 4, e[2] / 4, 255]);
+            }
+        }
+    }
+    (mismatches, image)
+}
+
+fn assert_golden(name: &str, actual: &RgbaImage) {
+    let reference = root().join("tests").join("golden").join(format!("{}.png", name));
+    if env::var_os("UPDATE_GOLDEN").is_some() {
+        save_png(&reference, actual);
+        return;
+    }
+    let expected = load_png(&reference);
+    let output = root().join("target").join("golden");
+    assert_eq!((actual.width, actual.height), (expected.width, expected.height),
+               "{}: frame size differs from the reference", name);
+    let (mismatches, diff_image) = diff(actual, &expected, TOLERANCE);
+    if mismatches > 0 {
+        save_png(&output.join(format!("{}.actual.png", name)), actual);
+        save_png(&output.join(format!("{}.diff.png", name)), &diff_image);
+        panic!("{}: {} pixels differ from {}, see {}", name, mismatches, reference.display(), output.display());
+    }
+}
+
+fn banner() -> RgbaImage {
+    // stand-in for the "Loading…" canvas, text can not be drawn without a browser
+    let mut image = RgbaImage::create(270, 80);
+    for y in 0..80 {
+        for x in 0..270 {
+            let text = (30..54).contains(&y) && (20..250).contains(&x) && (x / 12) % 2 == 0;
+            image.set_pixel(x, y, if text { [0, 0, 0, 255] } else { [211, 211, 211, 255] });
+        }
+    }
+    image
+}
+
+#[test]
+fn loading_banner() {
+    let projection = Projection::create(320, 240);
+    let mut renderer = CpuRenderer::create(320, 240);
+    let atlas = renderer.create_atlas(&[TextureSource::Rgba(banner())]).unwrap();
+    let sprites = [Sprite {
+        texture: 0,
+        position: Point { x: 160.0, y: 240.0 * 0.33 },
+        pivot: Point { x: 135.0, y: 40.0 },
+        rotation: 0.0,
+        width: 270.0,
+        height: 80.0,
+        alpha: 0.85,
+        ..Sprite::default()
+    }];
+    renderer.render(&projection, &sprites, &atlas);
+    assert_golden("loading", renderer.frame());
+}
+
+#[test]
+fn snowfall() {
+    let images: Vec<TextureSource> = SNOWFLAKES.iter()
+        .map(|name| TextureSource::Rgba(load_png(&root().join("img").join(name))))
+        .collect();
+    let textures: Vec<(u32, u32)> = images.iter().map(|image| image.size()).collect();
+    let projection = Projection::create(320, 240);
+    let mut renderer = CpuRenderer::create(320, 240);
+    let atlas = renderer.create_atlas(&images).unwrap();
+
+    let mut simulation = Simulation::create(320.0, 240.0);
+    let mut sprites = simulation.populate(&textures, &mut XorShift::create(2019));
+    let pointer = [None, Some(Point { x: 100.0, y: 100.0 }), Some(Point { x: 140.0, y: 90.0 }), None];
+    for p in pointer.iter() {
+        simulation.step(&mut sprites, 0.25, *p);
+    }
+    renderer.render(&projection, &sprites, &atlas);
+    assert_golden("snowfall", renderer.frame());
+}
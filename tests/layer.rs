@@ -0,0 +1,20 @@
+use kosygin::layer::{Layer, LayerSet};
+
+#[test]
+fn layers_are_found_by_name() {
+    for layer in Layer::ALL.iter() {
+        assert_eq!(Layer::from_name(layer.name()), Some(*layer));
+    }
+    assert_eq!(Layer::from_name("flakes"), Some(Layer::Flakes));
+    assert_eq!(Layer::from_name("sky"), None);
+}
+
+#[test]
+fn layer_set_toggles_single_layers() {
+    let mut set = LayerSet::all();
+    set.set(Layer::Overlay, false);
+    assert!(set.contains(Layer::Background) && set.contains(Layer::Flakes) && !set.contains(Layer::Overlay));
+    set.set(Layer::Overlay, true);
+    assert_eq!(set, LayerSet::all());
+    assert!(Layer::ALL.iter().all(|layer| !LayerSet::empty().contains(*layer)));
+}
@@ -0,0 +1,62 @@
+use kosygin::lifecycle::{ContextEvent, ContextLifecycle, ContextState, RECREATE_ATTEMPTS};
+
+#[test]
+fn lost_context_pauses_until_recreated() {
+    let mut lifecycle = ContextLifecycle::create();
+    assert!(lifecycle.should_render());
+    assert_eq!(lifecycle.handle(ContextEvent::Lost), ContextState::Lost);
+    assert!(!lifecycle.should_render());
+    assert_eq!(lifecycle.handle(ContextEvent::Restored), ContextState::Restoring);
+    assert!(!lifecycle.should_render());
+    assert_eq!(lifecycle.handle(ContextEvent::Recreated), ContextState::Active);
+    assert!(lifecycle.should_render());
+    assert_eq!(lifecycle.losses(), 1);
+}
+
+#[test]
+fn failed_recreation_waits_for_next_restore() {
+    let mut lifecycle = ContextLifecycle::create();
+    lifecycle.handle(ContextEvent::Lost);
+    lifecycle.handle(ContextEvent::Restored);
+    assert_eq!(lifecycle.handle(ContextEvent::RecreateFailed), ContextState::Lost);
+    assert_eq!(lifecycle.handle(ContextEvent::Restored), ContextState::Restoring);
+    assert_eq!(lifecycle.handle(ContextEvent::Lost), ContextState::Lost);
+    assert_eq!(lifecycle.losses(), 2);
+}
+
+#[test]
+fn unexpected_events_are_ignored() {
+    let mut lifecycle = ContextLifecycle::create();
+    assert_eq!(lifecycle.handle(ContextEvent::Restored), ContextState::Active);
+    assert_eq!(lifecycle.handle(ContextEvent::Recreated), ContextState::Active);
+    lifecycle.handle(ContextEvent::Lost);
+    assert_eq!(lifecycle.handle(ContextEvent::Lost), ContextState::Lost);
+    assert_eq!(lifecycle.handle(ContextEvent::Recreated), ContextState::Lost);
+    assert_eq!(lifecycle.losses(), 1);
+}
+
+#[test]
+fn failed_recreation_is_retried_a_few_times() {
+    let mut lifecycle = ContextLifecycle::create();
+    lifecycle.handle(ContextEvent::Lost);
+    assert!(!lifecycle.should_retry());
+    for _ in 1..RECREATE_ATTEMPTS {
+        lifecycle.handle(ContextEvent::Restored);
+        lifecycle.handle(ContextEvent::RecreateFailed);
+        assert!(lifecycle.should_retry());
+    }
+    lifecycle.handle(ContextEvent::Restored);
+    lifecycle.handle(ContextEvent::RecreateFailed);
+    assert!(!lifecycle.should_retry());
+
+    // a new loss brings a new restore event and new attempts
+    lifecycle.handle(ContextEvent::Restored);
+    lifecycle.handle(ContextEvent::Lost);
+    assert!(!lifecycle.should_retry());
+    lifecycle.handle(ContextEvent::Restored);
+    lifecycle.handle(ContextEvent::RecreateFailed);
+    assert!(lifecycle.should_retry());
+    lifecycle.handle(ContextEvent::Restored);
+    lifecycle.handle(ContextEvent::Recreated);
+    assert!(!lifecycle.should_retry());
+}
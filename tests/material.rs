@@ -0,0 +1,218 @@
+use kosygin::cpu_renderer::CpuRenderer;
+use kosygin::geom::Point;
+use kosygin::material::{AttributeType, Materials, Shader, UniformValue, DEFAULT_MATERIAL, DEFAULT_SHADER};
+use kosygin::renderer::{
+    batch_sprites, fragment_source, BatchOrder, Projection, RenderBackend, Sprite, FRAGMENT_HEADER, FRAGMENT_MAIN,
+    INSTANCED_FRAGMENT_HEADER, INSTANCED_FRAGMENT_MAIN,
+};
+use kosygin::texture::{RgbaImage, TextureSource};
+
+fn solid(width: u32, height: u32, color: [u8; 4]) -> RgbaImage {
+    let mut image = RgbaImage::create(width, height);
+    for y in 0..height {
+        for x in 0..width {
+            image.set_pixel(x, y, color);
+        }
+    }
+    image
+}
+
+fn square(x: f32, size: f32) -> Sprite {
+    Sprite {
+        position: Point { x, y: 10.0 },
+        pivot: Point { x: size * 0.5, y: size * 0.5 },
+        width: size,
+        height: size,
+        ..Sprite::default()
+    }
+}
+
+#[test]
+fn uniforms_are_checked() {
+    let mut materials = Materials::create();
+    let tint = materials.add_shader(Shader::tint()).unwrap();
+    let material = materials.add_material(tint).unwrap();
+    assert_eq!(materials.material(material).unwrap().values, vec![UniformValue::Vec4([1.0; 4])]);
+    assert!(materials.set_uniform(material, "u_color", UniformValue::Vec4([0.5; 4])).is_ok());
+    assert!(materials.set_uniform(material, "u_color", UniformValue::Float(0.5)).is_err());
+    assert!(materials.set_uniform(material, "u_radius", UniformValue::Float(0.5)).is_err());
+    assert!(materials.set_uniform(DEFAULT_MATERIAL, "u_color", UniformValue::Vec4([0.5; 4])).is_err());
+    assert!(materials.add_material(tint + 1).is_err());
+
+    let mut twice = Shader::tint();
+    twice.uniforms.push(twice.uniforms[0].clone());
+    assert!(materials.add_shader(twice).is_err());
+    let mut builtin = Shader::textured();
+    builtin.uniforms.push((String::from("u_matrix"), UniformValue::Float(0.0)));
+    assert!(materials.add_shader(builtin).is_err());
+    assert_eq!(materials.shaders().len(), 2);
+    assert_eq!(materials.shader(DEFAULT_SHADER).unwrap().name, "textured");
+}
+
+/// Texture alpha with the color of the sprite's `a_color` attribute.
+fn colored() -> Shader {
+    Shader {
+        name: String::from("colored"),
+        uniforms: Vec::new(),
+        attributes: vec![(String::from("a_color"), AttributeType::Vec4)],
+        glsl: String::from("vec4 shade(vec2 uv) { return vec4(a_color.rgb, texel(uv).a); }"),
+        cpu: |fragment| {
+            let [r, g, b, _] = fragment.attributes;
+            [r, g, b, fragment.texel(fragment.uv)[3]]
+        },
+    }
+}
+
+#[test]
+fn attributes_are_checked() {
+    let mut materials = Materials::create();
+    assert!(materials.add_shader(colored()).is_ok());
+    let mut too_many = colored();
+    too_many.attributes.push((String::from("a_size"), AttributeType::Float));
+    assert!(materials.add_shader(too_many).is_err());
+    let mut twice = colored();
+    twice.attributes = vec![(String::from("a_size"), AttributeType::Float), (String::from("a_size"), AttributeType::Vec2)];
+    assert!(materials.add_shader(twice).is_err());
+    let mut uniform = Shader::tint();
+    uniform.attributes.push((String::from("u_color"), AttributeType::Float));
+    assert!(materials.add_shader(uniform).is_err());
+    let mut builtin = colored();
+    builtin.attributes[0].0 = String::from("u_texelSize");
+    assert!(materials.add_shader(builtin).is_err());
+
+    let mut packed = colored();
+    packed.attributes = vec![(String::from("a_size"), AttributeType::Float), (String::from("a_offset"), AttributeType::Vec2)];
+    assert_eq!(packed.attribute_defines("v_attributes"), "#define a_size v_attributes.x\n#define a_offset v_attributes.yz\n");
+    assert!(materials.add_shader(packed).is_ok());
+}
+
+/// Identifiers of `source` with its `#define`s applied, directives left out.
+fn preprocessed_words(source: &str) -> Vec<String> {
+    let words = |line: &str| -> Vec<String> {
+        line.split(|c: char| !c.is_ascii_alphanumeric() && c != '_').filter(|word| !word.is_empty()).map(String::from).collect()
+    };
+    let mut defines = Vec::new();
+    let mut result = Vec::new();
+    for line in source.lines() {
+        let line = line.trim();
+        if let Some(define) = line.strip_prefix("#define ") {
+            let (name, value) = define.split_once(' ').unwrap();
+            defines.push((name.to_string(), words(value)));
+        } else if !line.starts_with('#') {
+            for word in words(line) {
+                match defines.iter().find(|(name, _)| *name == word) {
+                    Some((_, value)) => result.extend(value.iter().cloned()),
+                    None => result.push(word)
+                }
+            }
+        }
+    }
+    result
+}
+
+#[test]
+fn shader_bodies_compile_as_both_glsl_versions() {
+    let mut lookup = Shader::tint();
+    lookup.glsl = String::from("vec4 shade(vec2 uv) { // round the corner lookup
+  return texture2D(u_image, uv) * texture2DProj(u_image, vec3(uv, 1.0)) * u_color; }");
+    let shaders = [Shader::textured(), Shader::tint(), Shader::glow(), Shader::blur(), colored(), lookup];
+    let mut materials = Materials::create();
+    for shader in shaders.iter() {
+        assert!(materials.add_shader(shader.clone()).is_ok(), "{}", shader.name);
+        let es1 = preprocessed_words(&fragment_source(shader, FRAGMENT_HEADER, FRAGMENT_MAIN));
+        for word in ["texture", "textureProj", "fragColor", "flat", "layout"] {
+            assert!(!es1.iter().any(|w| w == word), "{} uses {} in WebGL 1", shader.name, word);
+        }
+        let es3 = preprocessed_words(&fragment_source(shader, INSTANCED_FRAGMENT_HEADER, INSTANCED_FRAGMENT_MAIN));
+        for word in ["texture2D", "texture2DProj", "varying", "attribute", "gl_FragColor"] {
+            assert!(!es3.iter().any(|w| w == word), "{} uses {} in WebGL 2", shader.name, word);
+        }
+        for es in [&es1, &es3] {
+            assert_eq!(es.windows(2).filter(|pair| pair[0] == "vec4" && pair[1] == "shade").count(), 1);
+        }
+    }
+    assert!(INSTANCED_FRAGMENT_HEADER.starts_with("#version 300 es\n"));
+
+    let mut keyword = Shader::tint();
+    keyword.glsl = String::from("vec4 shade(vec2 uv) { vec4 sample = texel(uv); return sample * u_color; }");
+    assert!(materials.add_shader(keyword).is_err());
+    let mut lookup = Shader::textured();
+    lookup.glsl = String::from("vec4 shade(vec2 uv) { return texture(u_image, uv); }");
+    assert!(materials.add_shader(lookup).is_err());
+    let mut uniform = Shader::tint();
+    uniform.uniforms[0].0 = String::from("round");
+    assert!(materials.add_shader(uniform).is_err());
+    let mut attribute = colored();
+    attribute.attributes[0].0 = String::from("fragColor");
+    assert!(materials.add_shader(attribute).is_err());
+}
+
+#[test]
+fn attributes_vary_per_sprite_within_a_batch() {
+    let projection = Projection::create(40, 20);
+    let mut renderer = CpuRenderer::create(40, 20);
+    let atlas = renderer.create_atlas(&[TextureSource::Rgba(solid(8, 8, [255, 255, 255, 255]))]).unwrap();
+    let shader = renderer.add_shader(colored()).unwrap();
+    let material = renderer.add_material(shader).unwrap();
+
+    let sprites = [Sprite { material, attributes: [1.0, 0.0, 0.0, 0.0], ..square(5.0, 8.0) },
+                   Sprite { material, attributes: [0.0, 1.0, 0.0, 0.0], ..square(15.0, 8.0) }];
+    renderer.render(&projection, &sprites, &atlas);
+    let frame = renderer.frame();
+    assert_eq!(frame.pixel(5, 10), [255, 0, 0, 255]);
+    assert_eq!(frame.pixel(15, 10), [0, 255, 0, 255]);
+    assert_eq!(renderer.frame_stats().draw_calls, 1);
+}
+
+#[test]
+fn renders_sprites_with_their_materials() {
+    let projection = Projection::create(40, 20);
+    let mut renderer = CpuRenderer::create(40, 20);
+    let atlas = renderer.create_atlas(&[TextureSource::Rgba(solid(8, 8, [255, 255, 255, 255]))]).unwrap();
+    let tint = renderer.add_shader(Shader::tint()).unwrap();
+    let red = renderer.add_material(tint).unwrap();
+    renderer.set_uniform(red, "u_color", UniformValue::Vec4([1.0, 0.0, 0.0, 1.0])).unwrap();
+    let blue = renderer.add_material(tint).unwrap();
+    renderer.set_uniform(blue, "u_color", UniformValue::Vec4([0.0, 0.0, 1.0, 1.0])).unwrap();
+
+    let sprites = [Sprite { material: red, ..square(5.0, 8.0) }, square(15.0, 8.0),
+                   Sprite { material: blue, ..square(25.0, 8.0) }, Sprite { material: red, ..square(35.0, 8.0) }];
+    let (_, batches) = batch_sprites(&sprites, &atlas, BatchOrder::Preserve);
+    assert_eq!(batches.iter().map(|batch| batch.material).collect::<Vec<_>>(), vec![red, DEFAULT_MATERIAL, blue, red]);
+    let (_, batches) = batch_sprites(&sprites, &atlas, BatchOrder::ByPage);
+    assert_eq!(batches.len(), 3);
+
+    renderer.render(&projection, &sprites, &atlas);
+    let frame = renderer.frame();
+    assert_eq!(frame.pixel(5, 10), [255, 0, 0, 255]);
+    assert_eq!(frame.pixel(15, 10), [255, 255, 255, 255]);
+    assert_eq!(frame.pixel(25, 10), [0, 0, 255, 255]);
+    assert_eq!(frame.pixel(35, 10), [255, 0, 0, 255]);
+    assert_eq!(renderer.frame_stats().draw_calls, 4);
+}
+
+#[test]
+fn blur_softens_edges() {
+    let mut image = RgbaImage::create(8, 8);
+    for y in 0..8 {
+        for x in 4..8 {
+            image.set_pixel(x, y, [255, 255, 255, 255]);
+        }
+    }
+    let projection = Projection::create(16, 16);
+    let mut renderer = CpuRenderer::create(16, 16);
+    let atlas = renderer.create_atlas(&[TextureSource::Rgba(image)]).unwrap();
+    let blur = renderer.add_shader(Shader::blur()).unwrap();
+    let blurred = renderer.add_material(blur).unwrap();
+    renderer.set_uniform(blurred, "u_radius", UniformValue::Float(1.0)).unwrap();
+    let mut sprite = Sprite { material: blurred, ..square(8.0, 16.0) };
+    sprite.position.y = 8.0;
+
+    renderer.render(&projection, &[Sprite { material: DEFAULT_MATERIAL, ..sprite.clone() }], &atlas);
+    let sharp = renderer.frame().pixel(7, 8)[0];
+    renderer.render(&projection, &[sprite], &atlas);
+    let soft = renderer.frame().pixel(7, 8)[0];
+    assert!(soft > sharp, "{} > {}", soft, sharp);
+    assert_eq!(renderer.frame().pixel(1, 8), [0, 0, 0, 255]);
+    assert_eq!(renderer.frame().pixel(14, 8), [255, 255, 255, 255]);
+}
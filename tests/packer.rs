@@ -0,0 +1,77 @@
+use kosygin::packer::{pack, AtlasConfig, PackError, PackedItem};
+
+fn config(max_size: u32, padding: u32, extrusion: u32, max_pages: usize) -> AtlasConfig {
+    AtlasConfig { max_size, padding, extrusion, max_pages }
+}
+
+/// Item area grown by extrusion and half of the padding on every side.
+fn cell(item: &PackedItem, config: &AtlasConfig) -> (i64, i64, i64, i64) {
+    let e = config.extrusion as i64;
+    let p = config.padding as i64;
+    (item.x as i64 - e, item.y as i64 - e, item.width as i64 + 2 * e + p, item.height as i64 + 2 * e + p)
+}
+
+fn assert_valid(sizes: &[(u32, u32)], config: &AtlasConfig) -> usize {
+    let packing = pack(sizes, config).unwrap();
+    assert_eq!(packing.items.len(), sizes.len());
+    for (page, &(width, height)) in packing.pages.iter().enumerate() {
+        assert!(width.is_power_of_two() && height.is_power_of_two());
+        assert!(width <= config.max_size && height <= config.max_size);
+        for (i, item) in packing.items.iter().enumerate().filter(|(_, item)| item.page == page) {
+            assert_eq!((item.width, item.height), sizes[i]);
+            assert!(item.x >= config.extrusion && item.y >= config.extrusion);
+            assert!(item.x + item.width + config.extrusion <= width);
+            assert!(item.y + item.height + config.extrusion <= height);
+        }
+    }
+    for (i, a) in packing.items.iter().enumerate() {
+        for b in packing.items.iter().skip(i + 1).filter(|b| b.page == a.page) {
+            let (ax, ay, aw, ah) = cell(a, config);
+            let (bx, by, bw, bh) = cell(b, config);
+            let overlap = ax < bx + bw && bx < ax + aw && ay < by + bh && by < ay + ah;
+            assert!(!overlap, "{:?} overlaps {:?}", a, b);
+        }
+    }
+    packing.pages.len()
+}
+
+#[test]
+fn packs_many_sprites_into_one_page() {
+    let sizes: Vec<(u32, u32)> = (0..48).map(|i| (128, 116 + (i % 4) * 10)).collect();
+    // 48 sprites of 128px would need a 6144px wide strip
+    let pages = assert_valid(&sizes, &config(2048, 2, 1, 1));
+    assert_eq!(pages, 1);
+}
+
+#[test]
+fn packs_mixed_sizes_without_overlap() {
+    let sizes = [(270, 80), (128, 146), (128, 142), (5, 300), (64, 64), (1, 1), (300, 5), (128, 116)];
+    assert_valid(&sizes, &config(512, 3, 2, 1));
+    assert_valid(&sizes, &config(512, 0, 0, 1));
+}
+
+#[test]
+fn page_is_rounded_to_power_of_2() {
+    let packing = pack(&[(100, 30)], &config(1024, 2, 1, 1)).unwrap();
+    assert_eq!(packing.pages, vec![(128, 32)]);
+    assert_eq!(packing.items[0], PackedItem { page: 0, x: 1, y: 1, width: 100, height: 30 });
+}
+
+#[test]
+fn spills_into_more_pages() {
+    let sizes = vec![(100, 100); 10];
+    let pages = assert_valid(&sizes, &config(256, 2, 1, 4));
+    assert_eq!(pages, 3);
+}
+
+#[test]
+fn reports_too_many_pages() {
+    let sizes = vec![(100, 100); 10];
+    assert_eq!(pack(&sizes, &config(256, 2, 1, 2)).unwrap_err(), PackError::TooManyPages { max_pages: 2 });
+}
+
+#[test]
+fn reports_too_large_item() {
+    let error = pack(&[(10, 10), (255, 10)], &config(256, 2, 1, 4)).unwrap_err();
+    assert_eq!(error, PackError::TooLarge { index: 1, width: 255, height: 10, max_size: 256 });
+}
@@ -0,0 +1,80 @@
+use kosygin::postprocess::{apply_effects, PostEffect, PostPass, MAX_BLUR_RADIUS};
+use kosygin::texture::RgbaImage;
+
+fn filled(width: u32, height: u32, color: [u8; 4]) -> RgbaImage {
+    let mut image = RgbaImage::create(width, height);
+    for y in 0..height {
+        for x in 0..width {
+            image.set_pixel(x, y, color);
+        }
+    }
+    image
+}
+
+#[test]
+fn blur_spreads_pixels_symmetrically() {
+    let mut image = filled(9, 9, [0, 0, 0, 255]);
+    image.set_pixel(4, 4, [255, 255, 255, 255]);
+    let blurred = apply_effects(&[PostEffect::Blur { radius: 2.0 }], image);
+    let center = blurred.pixel(4, 4);
+    assert!(center[0] < 255 && center[0] > 0);
+    assert_eq!(blurred.pixel(3, 4), blurred.pixel(5, 4));
+    assert_eq!(blurred.pixel(4, 3), blurred.pixel(4, 5));
+    assert_eq!(blurred.pixel(3, 4), blurred.pixel(4, 3));
+    assert_eq!(blurred.pixel(1, 4), [0, 0, 0, 255]);
+    assert!(blurred.pixel(3, 3)[0] > 0);
+
+    // flat areas stay flat up to the clamped edges
+    let flat = apply_effects(&[PostEffect::Blur { radius: 5.0 }], filled(6, 4, [40, 80, 120, 255]));
+    assert!(flat.pixels.chunks_exact(4).all(|pixel| pixel == [40, 80, 120, 255]));
+}
+
+#[test]
+fn bloom_only_spreads_bright_parts() {
+    let mut image = filled(12, 3, [0, 0, 0, 255]);
+    image.set_pixel(2, 1, [100, 100, 100, 255]);
+    image.set_pixel(9, 1, [255, 255, 255, 255]);
+    let bloom = PostEffect::Bloom { threshold: 0.5, radius: 2.0, intensity: 1.0 };
+    let result = apply_effects(&[bloom], image);
+    assert_eq!(result.pixel(2, 1), [100, 100, 100, 255]);
+    assert_eq!(result.pixel(3, 1), [0, 0, 0, 255]);
+    assert_eq!(result.pixel(9, 1), [255, 255, 255, 255]);
+    assert!(result.pixel(8, 1)[0] > 0);
+    assert_eq!(bloom.passes().len(), 3);
+}
+
+#[test]
+fn vignette_darkens_corners_only() {
+    let image = filled(20, 20, [200, 200, 200, 255]);
+    let result = apply_effects(&[PostEffect::Vignette { radius: 0.5, intensity: 1.0 }], image);
+    assert_eq!(result.pixel(10, 10), [200, 200, 200, 255]);
+    assert!(result.pixel(0, 0)[0] < 20);
+    assert_eq!(result.pixel(0, 0)[3], 255);
+    assert!(result.pixel(0, 10)[0] < 200 && result.pixel(0, 10)[0] > result.pixel(0, 0)[0]);
+}
+
+#[test]
+fn color_grade_works_on_straight_colors() {
+    let mut image = RgbaImage::create(2, 1);
+    image.set_pixel(0, 0, [200, 100, 50, 255]);
+    // premultiplied half transparent
+    image.set_pixel(1, 0, [100, 50, 25, 128]);
+    let neutral = PostEffect::ColorGrade { brightness: 0.0, contrast: 1.0, saturation: 1.0, tint: [1.0; 3] };
+    let result = apply_effects(&[neutral], image.clone());
+    assert_eq!(result.pixel(0, 0), [200, 100, 50, 255]);
+    assert_eq!(result.pixel(1, 0), [100, 50, 25, 128]);
+
+    let gray = PostEffect::ColorGrade { brightness: 0.0, contrast: 1.0, saturation: 0.0, tint: [1.0; 3] };
+    let result = apply_effects(&[gray], image);
+    let [r, g, b, a] = result.pixel(1, 0);
+    assert_eq!((r, a), (g, 128));
+    assert_eq!(g, b);
+}
+
+#[test]
+fn passes_keep_radius_within_the_shader_loop() {
+    let passes = PostEffect::Blur { radius: 100.0 }.passes();
+    assert_eq!(passes, vec![PostPass::Blur { horizontal: true, radius: MAX_BLUR_RADIUS, threshold: 0.0 },
+                            PostPass::Blur { horizontal: false, radius: MAX_BLUR_RADIUS, threshold: 0.0 }]);
+    assert_eq!(passes[1].params()[..3], [0.0, 1.0, MAX_BLUR_RADIUS]);
+}
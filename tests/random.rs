@@ -0,0 +1,56 @@
+use kosygin::random::{Rng, XorShift};
+use kosygin::simulation::Simulation;
+
+#[test]
+fn same_seed_same_sequence() {
+    let mut a = XorShift::create(12345);
+    let mut b = XorShift::create(12345);
+    let mut c = XorShift::create(12346);
+    let sa: Vec<u32> = (0..16).map(|_| a.next_u32()).collect();
+    let sb: Vec<u32> = (0..16).map(|_| b.next_u32()).collect();
+    let sc: Vec<u32> = (0..16).map(|_| c.next_u32()).collect();
+    assert_eq!(sa, sb);
+    assert_ne!(sa, sc);
+}
+
+#[test]
+fn zero_seed_is_usable() {
+    let mut rng = XorShift::create(0);
+    let values: Vec<u32> = (0..4).map(|_| rng.next_u32()).collect();
+    assert!(values.iter().any(|v| *v != 0));
+}
+
+#[test]
+fn range_stays_within_bounds() {
+    let mut rng = XorShift::create(99);
+    for _ in 0..10_000 {
+        let v = rng.range(-100.0, 100.0);
+        assert!((-100.0..100.0).contains(&v));
+    }
+}
+
+#[test]
+fn seeded_from_another_rng() {
+    let mut source_a = XorShift::create(5);
+    let mut source_b = XorShift::create(5);
+    let mut a = XorShift::from_rng(&mut source_a);
+    let mut b = XorShift::from_rng(&mut source_b);
+    assert_eq!(a.next_u32(), b.next_u32());
+}
+
+#[test]
+fn same_seed_same_snowflake_layout() {
+    let simulation = Simulation::create(640.0, 480.0);
+    let textures = [(32, 32), (32, 48), (48, 32)];
+    let a = simulation.populate(&textures, &mut XorShift::create(2019));
+    let b = simulation.populate(&textures, &mut XorShift::create(2019));
+    let c = simulation.populate(&textures, &mut XorShift::create(2020));
+    assert_eq!(a.len(), b.len());
+    for (sa, sb) in a.iter().zip(b.iter()) {
+        assert_eq!(sa.texture, sb.texture);
+        assert_eq!(sa.position.x, sb.position.x);
+        assert_eq!(sa.position.y, sb.position.y);
+        assert_eq!(sa.rotation, sb.rotation);
+    }
+    assert!(a.iter().zip(c.iter()).any(|(sa, sc)| sa.position.x != sc.position.x));
+}
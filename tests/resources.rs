@@ -0,0 +1,43 @@
+use kosygin::cpu_renderer::CpuRenderer;
+use kosygin::packer::AtlasConfig;
+use kosygin::renderer::{RenderBackend, ResourceCounts, TextureAtlas};
+use kosygin::resource_manager::Registry;
+use kosygin::texture::{RgbaImage, TextureSource};
+
+#[test]
+fn registry_reuses_removed_ids() {
+    let mut registry = Registry::create();
+    let a = registry.insert("a");
+    let b = registry.insert("b");
+    assert_eq!(registry.len(), 2);
+    assert_eq!(registry.remove(a), Some("a"));
+    assert_eq!(registry.remove(a), None);
+    assert_eq!(registry.get(a), None);
+    assert_eq!(registry.len(), 1);
+    let c = registry.insert("c");
+    assert_eq!(c, a);
+    assert_eq!(registry.get(b), Some(&"b"));
+    assert_eq!(registry.get(c), Some(&"c"));
+    assert_eq!(registry.drain().len(), 2);
+    assert!(registry.is_empty());
+}
+
+#[test]
+fn replaced_atlases_release_their_textures() {
+    let mut renderer = CpuRenderer::create(32, 32);
+    let mut config = AtlasConfig::create(16);
+    config.padding = 0;
+    config.extrusion = 0;
+    renderer.set_atlas_config(config);
+    let sources = [TextureSource::Rgba(RgbaImage::create(16, 16)), TextureSource::Rgba(RgbaImage::create(16, 16))];
+
+    let mut atlas = TextureAtlas::empty();
+    for _ in 0..10 {
+        let next = renderer.create_atlas(&sources).unwrap();
+        assert_eq!(next.pages().len(), 2);
+        renderer.delete_atlas(std::mem::replace(&mut atlas, next));
+        assert_eq!(renderer.resources(), ResourceCounts { textures: 2, buffers: 0, programs: 0 });
+    }
+    renderer.delete_atlas(atlas);
+    assert_eq!(renderer.resources(), ResourceCounts::default());
+}
@@ -0,0 +1,124 @@
+use core::f32::consts::PI;
+
+use kosygin::cpu_renderer::CpuRenderer;
+use kosygin::geom::Point;
+use kosygin::renderer::{Projection, RenderBackend, Sprite};
+use kosygin::scene::{Node, Scene, Transform};
+use kosygin::texture::{RgbaImage, TextureSource};
+
+fn close(a: Point, b: Point) -> bool {
+    (a.x - b.x).abs() < 1e-4 && (a.y - b.y).abs() < 1e-4
+}
+
+fn square(texture: usize, size: f32) -> Sprite {
+    Sprite {
+        texture,
+        pivot: Point { x: size * 0.5, y: size * 0.5 },
+        width: size,
+        height: size,
+        ..Sprite::default()
+    }
+}
+
+fn node(texture: usize, z: i32, visible: bool) -> Node {
+    let mut node = Node::sprite(square(texture, 1.0));
+    node.z = z;
+    node.visible = visible;
+    node
+}
+
+#[test]
+fn world_matrix_composes_parent_transforms() {
+    let mut scene = Scene::create();
+    let mut parent = Node::group();
+    parent.transform.translation = Point { x: 100.0, y: 50.0 };
+    parent.transform.rotation = PI * 0.5;
+    let parent = scene.add(scene.root(), parent).unwrap();
+    let mut child = Node::group();
+    child.transform.translation = Point { x: 10.0, y: 0.0 };
+    child.transform.scale = Point { x: 2.0, y: 3.0 };
+    let child = scene.add(parent, child).unwrap();
+
+    let world = scene.world_matrix(child);
+    assert!(close(world.transform_point(Point { x: 0.0, y: 0.0 }), Point { x: 100.0, y: 60.0 }));
+    assert!(close(world.transform_point(Point { x: 1.0, y: 0.0 }), Point { x: 100.0, y: 62.0 }));
+    assert!(close(world.transform_point(Point { x: 0.0, y: 1.0 }), Point { x: 97.0, y: 60.0 }));
+}
+
+#[test]
+fn skew_tilts_vertical_lines() {
+    let transform = Transform { skew: Point { x: PI * 0.25, y: 0.0 }, ..Transform::identity() };
+    assert!(close(transform.matrix().transform_point(Point { x: 0.0, y: 2.0 }), Point { x: 2.0, y: 2.0 }));
+}
+
+#[test]
+fn flatten_orders_by_z_and_skips_hidden_subtrees() {
+    let mut scene = Scene::create();
+    let root = scene.root();
+    let back = scene.add(root, node(1, 1, true)).unwrap();
+    scene.add(root, node(2, -1, true)).unwrap();
+    scene.add(back, node(3, 0, true)).unwrap();
+    let hidden = scene.add(root, node(4, 0, false)).unwrap();
+    scene.add(hidden, node(5, 0, true)).unwrap();
+
+    let textures: Vec<usize> = scene.flatten().iter().map(|sprite| sprite.texture).collect();
+    assert_eq!(textures, vec![2, 1, 3]);
+}
+
+#[test]
+fn remove_drops_whole_subtree() {
+    let mut scene = Scene::create();
+    let group = scene.add(scene.root(), Node::group()).unwrap();
+    let child = scene.add(group, Node::sprite(square(0, 1.0))).unwrap();
+    scene.add(child, Node::sprite(square(0, 1.0))).unwrap();
+    assert_eq!(scene.len(), 4);
+    scene.remove(group);
+    assert_eq!(scene.len(), 1);
+    assert!(scene.node(child).is_none());
+    assert!(scene.node(scene.root()).unwrap().children().is_empty());
+    assert!(scene.add(group, Node::group()).is_err());
+}
+
+#[test]
+fn removed_ids_stay_invalid_when_slots_are_reused() {
+    let mut scene = Scene::create();
+    let removed = scene.add(scene.root(), node(1, 0, true)).unwrap();
+    scene.remove(removed);
+    let reused = scene.add(scene.root(), node(2, 0, true)).unwrap();
+    assert_eq!(scene.len(), 2);
+    assert_ne!(removed, reused);
+    assert!(scene.node(removed).is_none());
+    assert!(scene.node_mut(removed).is_none());
+    assert!(scene.add(removed, Node::group()).is_err());
+    scene.remove(removed);
+    assert_eq!(scene.node(reused).unwrap().sprite.as_ref().unwrap().texture, 2);
+    assert_eq!(scene.node(scene.root()).unwrap().children(), &[reused]);
+}
+
+#[test]
+fn renders_child_sprites_in_parent_space() {
+    let mut image = RgbaImage::create(2, 2);
+    for (x, y) in [(0, 0), (1, 0), (0, 1), (1, 1)].iter() {
+        image.set_pixel(*x, *y, [255, 255, 255, 255]);
+    }
+    let projection = Projection::create(40, 40);
+    let mut renderer = CpuRenderer::create(40, 40);
+    let atlas = renderer.create_atlas(&[TextureSource::Rgba(image)]).unwrap();
+
+    let mut scene = Scene::create();
+    let mut globe = Node::group();
+    globe.transform.translation = Point { x: 20.0, y: 20.0 };
+    globe.transform.scale = Point { x: 2.0, y: 2.0 };
+    let globe = scene.add(scene.root(), globe).unwrap();
+    let mut ornament = Node::sprite(square(0, 4.0));
+    ornament.transform.translation = Point { x: 5.0, y: 0.0 };
+    scene.add(globe, ornament).unwrap();
+
+    renderer.render_scene(&projection, &scene, &atlas);
+    let frame = renderer.frame();
+    // the 4x4 ornament ends up 8x8 around (30, 20)
+    assert_eq!(frame.pixel(27, 17), [255, 255, 255, 255]);
+    assert_eq!(frame.pixel(33, 23), [255, 255, 255, 255]);
+    assert_eq!(frame.pixel(25, 20), [0, 0, 0, 255]);
+    assert_eq!(frame.pixel(20, 20), [0, 0, 0, 255]);
+}
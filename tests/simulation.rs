@@ -0,0 +1,98 @@
+use kosygin::geom::Point;
+use kosygin::random::XorShift;
+use kosygin::renderer::Sprite;
+use kosygin::simulation::Simulation;
+
+const TEXTURES: [(u32, u32); 2] = [(64, 64), (64, 32)];
+
+fn flake(x: f32, y: f32) -> Sprite {
+    Sprite {
+        texture: 0,
+        position: Point { x, y },
+        pivot: Point { x: 10.0, y: 10.0 },
+        rotation: 0.0,
+        width: 20.0,
+        height: 20.0,
+        alpha: 1.0,
+        ..Sprite::default()
+    }
+}
+
+#[test]
+fn populate_fills_area_with_density() {
+    let simulation = Simulation::create(1000.0, 500.0);
+    let sprites = simulation.populate(&TEXTURES, &mut XorShift::create(7));
+    assert_eq!(sprites.len(), 300);
+    for sprite in sprites.iter() {
+        assert!(sprite.texture < TEXTURES.len());
+        assert!(sprite.position.x >= -100.0 && sprite.position.x < 1100.0);
+        assert!(sprite.position.y >= -100.0 && sprite.position.y < 600.0);
+    }
+}
+
+#[test]
+fn step_is_deterministic() {
+    let mut a = Simulation::create(800.0, 600.0);
+    let mut b = Simulation::create(800.0, 600.0);
+    let mut sprites_a = a.populate(&TEXTURES, &mut XorShift::create(42));
+    let mut sprites_b = b.populate(&TEXTURES, &mut XorShift::create(42));
+    let pointer = [None, Some(Point { x: 10.0, y: 10.0 }), Some(Point { x: 60.0, y: 30.0 }), None];
+    for p in pointer.iter() {
+        a.step(&mut sprites_a, 0.016, *p);
+        b.step(&mut sprites_b, 0.016, *p);
+    }
+    for (sa, sb) in sprites_a.iter().zip(sprites_b.iter()) {
+        assert_eq!(sa.position.x, sb.position.x);
+        assert_eq!(sa.position.y, sb.position.y);
+        assert_eq!(sa.rotation, sb.rotation);
+    }
+}
+
+#[test]
+fn snowflakes_fall_and_wrap_around() {
+    let mut simulation = Simulation::create(800.0, 600.0);
+    let mut sprites = vec![flake(400.0, 300.0), flake(400.0, 699.0)];
+    simulation.step(&mut sprites, 0.5, None);
+    assert!(sprites[0].position.y > 300.0);
+    assert!(sprites[0].position.x > 400.0);
+    assert!(sprites[1].position.y < 0.0);
+}
+
+#[test]
+fn dragging_pointer_creates_wind_that_decays() {
+    let mut simulation = Simulation::create(800.0, 600.0);
+    let mut sprites = vec![flake(400.0, 300.0)];
+    simulation.step(&mut sprites, 0.1, Some(Point { x: 100.0, y: 100.0 }));
+    simulation.step(&mut sprites, 0.1, Some(Point { x: 500.0, y: 100.0 }));
+    let wind = simulation.wind();
+    assert!(wind.x > 0.0);
+    assert_eq!(wind.y, 0.0);
+    simulation.step(&mut sprites, 0.1, None);
+    assert!(simulation.wind().x < wind.x);
+    simulation.step(&mut sprites, 2.0, None);
+    assert_eq!(simulation.wind().x, 0.0);
+}
+
+#[test]
+fn resize_scales_positions_and_keeps_density() {
+    let mut simulation = Simulation::create(1000.0, 500.0);
+    let mut rng = XorShift::create(7);
+    let mut sprites = simulation.populate(&TEXTURES, &mut rng);
+    let first = sprites[0].position;
+
+    simulation.resize(&mut sprites, 2000.0, 500.0, &TEXTURES, &mut rng);
+    assert_eq!(sprites.len(), 600);
+    assert!(sprites.iter().any(|s| s.position.x == (first.x + 100.0) * 2200.0 / 1200.0 - 100.0));
+    assert!(sprites.windows(2).all(|pair| pair[0].width <= pair[1].width));
+    for sprite in sprites.iter() {
+        assert!(sprite.position.x >= -100.0 && sprite.position.x < 2100.0);
+    }
+
+    simulation.resize(&mut sprites, 500.0, 500.0, &TEXTURES, &mut rng);
+    assert_eq!(sprites.len(), 150);
+    assert!(sprites.windows(2).all(|pair| pair[0].width <= pair[1].width));
+    assert!(sprites[0].width < 20.0 && sprites[149].width > 90.0);
+    for sprite in sprites.iter() {
+        assert!(sprite.position.x >= -100.0 && sprite.position.x < 600.0);
+    }
+}
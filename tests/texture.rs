@@ -0,0 +1,64 @@
+use kosygin::packer::AtlasConfig;
+use kosygin::renderer::compose_atlas;
+use kosygin::texture::{RgbaImage, TextureSource};
+
+/// Image with a distinct color in every pixel.
+fn gradient(width: u32, height: u32) -> RgbaImage {
+    let mut image = RgbaImage::create(width, height);
+    for y in 0..height {
+        for x in 0..width {
+            image.set_pixel(x, y, [(x * 10) as u8, (y * 10) as u8, 100, 255]);
+        }
+    }
+    image
+}
+
+#[test]
+fn extruded_repeats_edge_pixels() {
+    let image = gradient(3, 2);
+    let extruded = image.extruded(2);
+    assert_eq!((extruded.width, extruded.height), (7, 6));
+    for y in 0..2 {
+        for x in 0..3 {
+            assert_eq!(extruded.pixel(x + 2, y + 2), image.pixel(x, y));
+        }
+    }
+    // edges repeat the outermost row or column, corners the corner pixel
+    assert_eq!(extruded.pixel(3, 0), image.pixel(1, 0));
+    assert_eq!(extruded.pixel(3, 5), image.pixel(1, 1));
+    assert_eq!(extruded.pixel(0, 3), image.pixel(0, 1));
+    assert_eq!(extruded.pixel(6, 2), image.pixel(2, 0));
+    assert_eq!(extruded.pixel(0, 0), image.pixel(0, 0));
+    assert_eq!(extruded.pixel(6, 0), image.pixel(2, 0));
+    assert_eq!(extruded.pixel(0, 5), image.pixel(0, 1));
+    assert_eq!(extruded.pixel(6, 5), image.pixel(2, 1));
+
+    assert_eq!(image.extruded(0).pixels, image.pixels);
+    let single = gradient(1, 1).extruded(1);
+    assert!(single.pixels.chunks(4).all(|pixel| pixel == single.pixel(1, 1)));
+}
+
+/// ImageData and DOM sources need a browser, pixel sources of mixed sizes spilling into
+/// several pages cover what is composed without a canvas.
+#[test]
+fn composes_pixel_sources_into_pages_with_extrusion() {
+    let config = AtlasConfig { max_size: 32, padding: 1, extrusion: 2, max_pages: 4 };
+    let images = [gradient(20, 6), gradient(3, 5), gradient(24, 24), gradient(1, 1)];
+    let sources: Vec<TextureSource> = images.iter().cloned().map(TextureSource::Rgba).collect();
+    let (atlas, pages) = compose_atlas(&sources, &config).unwrap();
+    assert!(pages.len() > 1);
+    assert!(pages.iter().all(|page| page.canvas.is_none()));
+    assert_eq!(pages.iter().map(|page| page.pixels.len()).sum::<usize>(), images.len());
+
+    for (texture, image) in images.iter().enumerate() {
+        let (page, item) = atlas.region(texture);
+        let content = &pages[item.page];
+        assert_eq!((content.width, content.height), (page.width, page.height));
+        assert_eq!((item.width, item.height), (image.width, image.height));
+        let (x, y, extruded) = content.pixels.iter()
+            .find(|(x, y, _)| (*x, *y) == (item.x - 2, item.y - 2))
+            .unwrap();
+        assert!(x + extruded.width <= page.width && y + extruded.height <= page.height);
+        assert_eq!(extruded.pixels, image.extruded(2).pixels);
+    }
+}